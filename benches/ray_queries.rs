@@ -0,0 +1,81 @@
+// Compares ray queries through the level's bounding volume tree against
+// checking every entity, on floors covered in wall tiles.
+//
+//     cargo bench --bench ray_queries
+
+extern crate another_rogue;
+
+use std::time::{Duration, Instant};
+
+use another_rogue::vec2::Vec2;
+use another_rogue::ray::Ray;
+use another_rogue::entity::{Level, make_wall, make_enemy};
+use another_rogue::collision::nearest_ray_intersection;
+use another_rogue::tunables::Tunables;
+
+const TILE_SIZE: f32 = 32.0;
+const RAYS: usize = 2000;
+
+// A square floor with every other tile a wall, plus some enemies
+fn build_level(tiles_per_side: usize) -> Level {
+    let tunables = Tunables::new();
+    let size = tiles_per_side as f32 * TILE_SIZE * 2.0;
+    let mut level = Level::new(size, size, 1, tunables);
+
+    for x in 0..tiles_per_side {
+        for y in 0..tiles_per_side {
+            let position = Vec2::new((x as f32 * 2.0 + 1.5) * TILE_SIZE, (y as f32 * 2.0 + 1.5) * TILE_SIZE);
+            level.insert(make_wall(TILE_SIZE, TILE_SIZE, position));
+        }
+    }
+
+    for _ in 0..tiles_per_side * 2 {
+        let position = Vec2::new(level.rng.range(0.0, size), level.rng.range(0.0, size));
+        level.insert(make_enemy(&tunables, position));
+    }
+
+    level
+}
+
+fn rays(level: &mut Level) -> Vec<Ray> {
+    (0..RAYS).map(|_| {
+        let origin = Vec2::new(level.rng.range(0.0, level.width), level.rng.range(0.0, level.height));
+        let direction = Vec2::new(level.rng.range(-1.0, 1.0), level.rng.range(-1.0, 1.0));
+        Ray::new(origin, direction)
+    }).collect()
+}
+
+fn time<F: FnMut() -> usize>(mut f: F) -> (Duration, usize) {
+    let start = Instant::now();
+    let hits = f();
+    (start.elapsed(), hits)
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1_000_000.0
+}
+
+fn main() {
+    println!("{:>8} {:>14} {:>14} {:>9}", "walls", "every entity", "tree", "speedup");
+
+    for &tiles_per_side in &[16, 32, 64, 100] {
+        let mut level = build_level(tiles_per_side);
+        let rays = rays(&mut level);
+
+        let (brute_time, brute_hits) = time(|| {
+            rays.iter().filter(|ray| nearest_ray_intersection(ray, level.collision_entities(), |_, _| true).is_some()).count()
+        });
+
+        let (tree_time, tree_hits) = time(|| {
+            rays.iter().filter(|ray| level.ray_cast(ray, |_, _| true).is_some()).count()
+        });
+
+        assert_eq!(brute_hits, tree_hits, "Tree and brute force disagree");
+
+        println!("{:>8} {:>12.2}ms {:>12.2}ms {:>8.1}x",
+                 tiles_per_side * tiles_per_side,
+                 millis(brute_time),
+                 millis(tree_time),
+                 millis(brute_time) / millis(tree_time));
+    }
+}
@@ -0,0 +1,7 @@
+
+#[derive(Debug, Copy, Clone)]
+pub enum Ai {
+    // Charges its gun while it can see the player and fires once the
+    // charge has built up for charge_time ms
+    Turret { charge_time: u32 }
+}
@@ -1,5 +1,8 @@
 use sdl2::pixels::Color;
 
+use store::EntityId;
+use tunables::Tunables;
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum BulletType {
     PewPew,
@@ -8,12 +11,21 @@ pub enum BulletType {
 
 #[derive(Debug, Copy, Clone)]
 pub struct Bullet {
-    pub bullet_type: BulletType
+    pub bullet_type: BulletType,
+    pub owner: EntityId
 }
 
 impl Bullet {
-    pub fn new(bullet_type: BulletType) -> Bullet {
-        Bullet { bullet_type: bullet_type }
+    pub fn new(bullet_type: BulletType, owner: EntityId) -> Bullet {
+        Bullet { bullet_type, owner }
+    }
+
+    // Health taken from whatever the bullet gets through to
+    pub fn damage(&self, tunables: &Tunables) -> f32 {
+        match self.bullet_type {
+            BulletType::PewPew => tunables.pew_pew_damage,
+            BulletType::Boom => tunables.boom_damage
+        }
     }
 
     pub fn color(&self) -> Color {
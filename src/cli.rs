@@ -0,0 +1,108 @@
+use std::path::PathBuf;
+
+use another_rogue::tunables::MAX_FPS;
+
+pub const USAGE: &str = "Usage: another_rogue [options]
+
+Options:
+    --seed <n>          Seed for everything random, defaults to the time
+    --level <path>      Play the given level file first
+    --width <px>        Window and level width, defaults to 800
+    --height <px>       Window and level height, defaults to 600
+    --fullscreen        Run fullscreen
+    --fps <n>           Frame rate to draw at, overrides the tunables file
+    --headless          Run the simulation without a window
+    --ticks <n>         Ticks to run for with --headless
+    --replay <path>     Play back a recorded replay
+    --help              Show this message";
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub seed: Option<u64>,
+    pub level: Option<PathBuf>,
+    pub width: u32,
+    pub height: u32,
+    pub fullscreen: bool,
+    pub fps: Option<u32>,
+    pub headless: bool,
+    pub ticks: Option<u32>,
+    pub replay: Option<PathBuf>,
+    pub help: bool
+}
+
+impl Options {
+    pub fn new() -> Options {
+        Options {
+            seed: None,
+            level: None,
+            width: 800,
+            height: 600,
+            fullscreen: false,
+            fps: None,
+            headless: false,
+            ticks: None,
+            replay: None,
+            help: false
+        }
+    }
+}
+
+pub fn parse<I: Iterator<Item=String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" => options.seed = Some(number(&arg, args.next())?),
+            "--level" => options.level = Some(PathBuf::from(value(&arg, args.next())?)),
+            "--width" => options.width = number(&arg, args.next())?,
+            "--height" => options.height = number(&arg, args.next())?,
+            "--fullscreen" => options.fullscreen = true,
+            "--fps" => options.fps = Some(number(&arg, args.next())?),
+            "--headless" => options.headless = true,
+            "--ticks" => options.ticks = Some(number(&arg, args.next())?),
+            "--replay" => options.replay = Some(PathBuf::from(value(&arg, args.next())?)),
+            "--help" | "-h" => options.help = true,
+            _ => return Err(format!("Unknown option '{}'", arg))
+        }
+    }
+
+    if options.headless && options.ticks.is_none() && options.replay.is_none() {
+        return Err("--headless needs --ticks or --replay".to_string());
+    }
+
+    if options.width == 0 || options.height == 0 || options.fps == Some(0) {
+        return Err("--width, --height and --fps must be above zero".to_string());
+    }
+
+    if options.fps.is_some_and(|fps| fps > MAX_FPS) {
+        return Err(format!("--fps can't be above {}", MAX_FPS));
+    }
+
+    Ok(options)
+}
+
+fn value(arg: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or(format!("{} needs a value", arg))
+}
+
+fn number<T: ::std::str::FromStr>(arg: &str, value: Option<String>) -> Result<T, String> {
+    let value = self::value(arg, value)?;
+    value.parse::<T>().map_err(|_| format!("{} needs a number, got '{}'", arg, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &str) -> Result<Options, String> {
+        parse(args.split_whitespace().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn fps_must_be_in_range() {
+        assert!(parse_args("--fps 0").is_err());
+        assert!(parse_args("--fps 201").is_err());
+        assert!(parse_args("--fps 4294967295").is_err());
+        assert_eq!(parse_args("--fps 200").unwrap().fps, Some(200));
+    }
+}
@@ -1,28 +1,490 @@
 use std::f32;
+use std::cmp::Ordering;
+use std::collections::{HashMap, BinaryHeap};
 
 use vec2::Vec2;
-use entity::{Physics, Entity};
-use line::LineSegment;
+use entity::Entity;
 use ray::Ray;
-use shape::{AABB, Circle, CollisionShape};
+use line::LineSegment;
+use shape::{AABB, Circle, Polygon, Capsule, CollisionShape, MAX_POLYGON_VERTICES};
+use store::EntityId;
 
 
 #[derive(Debug, Copy, Clone)]
 pub struct Manifold {
     pub penetration: f32,
-    pub normal: Vec2
+    pub normal: Vec2,
+    // Where the impulse pushes, halfway between the two surfaces
+    pub contact: Vec2
 }
 
 impl Manifold {
-    pub fn new(penetration: f32, normal: Vec2) -> Manifold {
-        Manifold { penetration: penetration, normal: normal }
+    pub fn new(penetration: f32, normal: Vec2, contact: Vec2) -> Manifold {
+        Manifold { penetration, normal, contact }
+    }
+}
+
+// Corners closer than this along the normal count as the same face, so
+// boxes resting flat push in the middle of the face instead of on a corner
+const CONTACT_TOLERANCE: f32 = 0.5;
+
+// Uniform grid broadphase. Bodies go into every cell their bounds touch and
+// only bodies sharing a cell with overlapping bounds come out as candidate
+// pairs. Two fixed bodies, static ones that aren't kinematic or sensors,
+// never move into each other so they're never paired.
+pub struct SpatialHash {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+    bodies: Vec<(AABB, bool)>
+}
+
+impl SpatialHash {
+    pub fn new(cell_size: f32) -> SpatialHash {
+        SpatialHash { cell_size, cells: HashMap::new(), bodies: vec![] }
+    }
+
+    // Bodies are numbered in insertion order, starting from 0
+    pub fn insert(&mut self, bounds: AABB, is_fixed: bool) -> usize {
+        let index = self.bodies.len();
+        self.bodies.push((bounds, is_fixed));
+
+        let (min_x, min_y) = self.cell(bounds.min);
+        let (max_x, max_y) = self.cell(bounds.max);
+
+        for x in min_x..max_x + 1 {
+            for y in min_y..max_y + 1 {
+                self.cells.entry((x, y)).or_default().push(index);
+            }
+        }
+
+        index
+    }
+
+    // Candidate pairs (a, b) with a < b, sorted, so callers see pairs in the
+    // same order as checking every pair would
+    pub fn pairs(&self) -> Vec<(usize, usize)> {
+        let mut pairs = vec![];
+
+        for cell in self.cells.values() {
+            for (i, &a) in cell.iter().enumerate() {
+                for &b in &cell[i + 1..] {
+                    let (a_bounds, a_fixed) = self.bodies[a];
+                    let (b_bounds, b_fixed) = self.bodies[b];
+
+                    if !(a_fixed && b_fixed) && a_bounds.overlaps(&b_bounds) {
+                        pairs.push((a.min(b), a.max(b)));
+                    }
+                }
+            }
+        }
+
+        // Bodies sharing more than one cell show up once per cell
+        pairs.sort();
+        pairs.dedup();
+        pairs
+    }
+
+    fn cell(&self, point: Vec2) -> (i32, i32) {
+        ((point.x / self.cell_size).floor() as i32, (point.y / self.cell_size).floor() as i32)
+    }
+}
+
+const NULL_NODE: usize = usize::MAX;
+
+// How far a leaf's bounds reach past the body, so bodies can move a little
+// without the tree changing
+const TREE_MARGIN: f32 = 8.0;
+
+#[derive(Debug, Clone)]
+struct TreeNode<T> {
+    bounds: AABB,
+    parent: usize,
+    // Both NULL_NODE for leaves
+    children: [usize; 2],
+    // Leaves are 0, free nodes -1
+    height: i32,
+    data: Option<T>
+}
+
+impl<T> TreeNode<T> {
+    fn is_leaf(&self) -> bool {
+        self.children[0] == NULL_NODE
+    }
+}
+
+// Dynamic bounding volume tree, built the same way as Box2D's. Every body is
+// a leaf with slightly fattened bounds and every branch bounds its two
+// children. Inserts pick the sibling that grows the tree's perimeter the
+// least and rotations keep it balanced, so queries only visit the branches
+// that could matter.
+pub struct DynamicTree<T: Copy> {
+    nodes: Vec<TreeNode<T>>,
+    root: usize,
+    free: Vec<usize>
+}
+
+// A node waiting to be visited by a ray cast, nearest first
+struct RayCandidate {
+    distance: f32,
+    node: usize
+}
+
+impl PartialEq for RayCandidate {
+    fn eq(&self, other: &RayCandidate) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for RayCandidate {}
+
+impl PartialOrd for RayCandidate {
+    fn partial_cmp(&self, other: &RayCandidate) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RayCandidate {
+    // Reversed so the heap hands out the nearest node first
+    fn cmp(&self, other: &RayCandidate) -> Ordering {
+        other.distance.partial_cmp(&self.distance).unwrap_or(Ordering::Equal)
+            .then(other.node.cmp(&self.node))
+    }
+}
+
+impl<T: Copy> Default for DynamicTree<T> {
+    fn default() -> DynamicTree<T> {
+        DynamicTree::new()
+    }
+}
+
+impl<T: Copy> DynamicTree<T> {
+    pub fn new() -> DynamicTree<T> {
+        DynamicTree { nodes: vec![], root: NULL_NODE, free: vec![] }
+    }
+
+    // Returns the proxy used to update or remove the body later
+    pub fn insert(&mut self, bounds: AABB, data: T) -> usize {
+        let leaf = self.allocate();
+        self.nodes[leaf].bounds = bounds.expanded(TREE_MARGIN);
+        self.nodes[leaf].data = Some(data);
+        self.nodes[leaf].height = 0;
+
+        self.insert_leaf(leaf);
+        leaf
+    }
+
+    pub fn remove(&mut self, proxy: usize) {
+        self.remove_leaf(proxy);
+        self.release(proxy);
+    }
+
+    // Moves a body's leaf, returns whether the tree had to change. Bodies
+    // still inside their fattened bounds are left where they are.
+    pub fn update(&mut self, proxy: usize, bounds: AABB) -> bool {
+        if self.nodes[proxy].bounds.contains(&bounds) {
+            return false;
+        }
+
+        self.remove_leaf(proxy);
+        self.nodes[proxy].bounds = bounds.expanded(TREE_MARGIN);
+        self.insert_leaf(proxy);
+        true
+    }
+
+    pub fn data(&self, proxy: usize) -> T {
+        self.nodes[proxy].data.expect("Not a tree proxy")
+    }
+
+    pub fn height(&self) -> i32 {
+        if self.root == NULL_NODE { 0 } else { self.nodes[self.root].height }
+    }
+
+    // Calls back with every body whose fattened bounds overlap the given ones
+    pub fn query<F: FnMut(T)>(&self, bounds: &AABB, mut callback: F) {
+        let mut stack = vec![self.root];
+
+        while let Some(index) = stack.pop() {
+            if index == NULL_NODE {
+                continue;
+            }
+
+            let node = &self.nodes[index];
+            if !node.bounds.overlaps(bounds) {
+                continue;
+            }
+
+            if node.is_leaf() {
+                callback(node.data.unwrap());
+            } else {
+                stack.push(node.children[0]);
+                stack.push(node.children[1]);
+            }
+        }
+    }
+
+    // Visits leaves in the order the ray enters their bounds, asking the
+    // callback for the distance to an exact hit, and stops once nothing left
+    // could be nearer than the nearest hit so far. Hits the same distance
+    // away go to the smallest data.
+    pub fn ray_cast<R, F>(&self, ray: &Ray, callback: F) -> Option<(T, R)>
+        where T: Ord, F: FnMut(T) -> Option<(f32, R)> {
+
+        self.sweep(ray, 0.0, f32::INFINITY, callback)
+    }
+
+    // Ray cast for a circle of the given radius travelling along the ray, no
+    // further than max_distance
+    pub fn sweep<R, F>(&self, ray: &Ray, radius: f32, max_distance: f32, mut callback: F) -> Option<(T, R)>
+        where T: Ord, F: FnMut(T) -> Option<(f32, R)> {
+
+        let mut nearest: Option<(f32, T, R)> = None;
+        let mut candidates = BinaryHeap::new();
+
+        if self.root != NULL_NODE {
+            if let Some(distance) = ray.box_entry_distance(&self.nodes[self.root].bounds.expanded(radius)) {
+                candidates.push(RayCandidate { distance, node: self.root });
+            }
+        }
+
+        while let Some(candidate) = candidates.pop() {
+            if candidate.distance > max_distance {
+                break;
+            }
+
+            if let Some((nearest_distance, _, _)) = nearest {
+                if candidate.distance > nearest_distance {
+                    break;
+                }
+            }
+
+            let node = &self.nodes[candidate.node];
+
+            if node.is_leaf() {
+                let data = node.data.unwrap();
+                if let Some((distance, result)) = callback(data) {
+                    let is_nearer = match nearest {
+                        Some((nearest_distance, nearest_data, _)) =>
+                            distance < nearest_distance || (distance == nearest_distance && data < nearest_data),
+                        None => true
+                    };
+
+                    if is_nearer {
+                        nearest = Some((distance, data, result));
+                    }
+                }
+            } else {
+                for &child in &node.children {
+                    if let Some(distance) = ray.box_entry_distance(&self.nodes[child].bounds.expanded(radius)) {
+                        candidates.push(RayCandidate { distance, node: child });
+                    }
+                }
+            }
+        }
+
+        nearest.map(|(_, data, result)| (data, result))
+    }
+
+    fn allocate(&mut self) -> usize {
+        let node = TreeNode {
+            bounds: AABB::new(Vec2::new(0.0, 0.0), Vec2::new(0.0, 0.0)),
+            parent: NULL_NODE,
+            children: [NULL_NODE, NULL_NODE],
+            height: 0,
+            data: None
+        };
+
+        match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = node;
+                index
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    fn release(&mut self, index: usize) {
+        self.nodes[index].height = -1;
+        self.nodes[index].data = None;
+        self.free.push(index);
+    }
+
+    fn insert_leaf(&mut self, leaf: usize) {
+        if self.root == NULL_NODE {
+            self.root = leaf;
+            self.nodes[leaf].parent = NULL_NODE;
+            return;
+        }
+
+        // Walk down to the cheapest sibling
+        let leaf_bounds = self.nodes[leaf].bounds;
+        let mut index = self.root;
+
+        while !self.nodes[index].is_leaf() {
+            let node = &self.nodes[index];
+            let perimeter = node.bounds.perimeter();
+            let combined_perimeter = node.bounds.union(&leaf_bounds).perimeter();
+
+            // Cost of making a new parent for this node and the leaf
+            let cost = 2.0 * combined_perimeter;
+            // Every ancestor grows by this much if the leaf goes any deeper
+            let inheritance_cost = 2.0 * (combined_perimeter - perimeter);
+
+            let child_cost = |child: &TreeNode<T>| {
+                let combined = child.bounds.union(&leaf_bounds).perimeter();
+                if child.is_leaf() {
+                    combined + inheritance_cost
+                } else {
+                    combined - child.bounds.perimeter() + inheritance_cost
+                }
+            };
+
+            let cost_0 = child_cost(&self.nodes[node.children[0]]);
+            let cost_1 = child_cost(&self.nodes[node.children[1]]);
+
+            if cost < cost_0 && cost < cost_1 {
+                break;
+            }
+
+            index = if cost_0 < cost_1 { node.children[0] } else { node.children[1] };
+        }
+
+        let sibling = index;
+        let old_parent = self.nodes[sibling].parent;
+        let new_parent = self.allocate();
+
+        self.nodes[new_parent].parent = old_parent;
+        self.nodes[new_parent].bounds = leaf_bounds.union(&self.nodes[sibling].bounds);
+        self.nodes[new_parent].height = self.nodes[sibling].height + 1;
+        self.nodes[new_parent].children = [sibling, leaf];
+        self.nodes[sibling].parent = new_parent;
+        self.nodes[leaf].parent = new_parent;
+
+        if old_parent == NULL_NODE {
+            self.root = new_parent;
+        } else if self.nodes[old_parent].children[0] == sibling {
+            self.nodes[old_parent].children[0] = new_parent;
+        } else {
+            self.nodes[old_parent].children[1] = new_parent;
+        }
+
+        self.refit(self.nodes[leaf].parent);
+    }
+
+    fn remove_leaf(&mut self, leaf: usize) {
+        if leaf == self.root {
+            self.root = NULL_NODE;
+            return;
+        }
+
+        let parent = self.nodes[leaf].parent;
+        let grandparent = self.nodes[parent].parent;
+        let sibling = if self.nodes[parent].children[0] == leaf {
+            self.nodes[parent].children[1]
+        } else {
+            self.nodes[parent].children[0]
+        };
+
+        // The sibling takes the parent's place
+        if grandparent == NULL_NODE {
+            self.root = sibling;
+            self.nodes[sibling].parent = NULL_NODE;
+        } else {
+            if self.nodes[grandparent].children[0] == parent {
+                self.nodes[grandparent].children[0] = sibling;
+            } else {
+                self.nodes[grandparent].children[1] = sibling;
+            }
+            self.nodes[sibling].parent = grandparent;
+        }
+
+        self.release(parent);
+        self.refit(grandparent);
+    }
+
+    // Rebalances and fixes bounds and heights from a node up to the root
+    fn refit(&mut self, mut index: usize) {
+        while index != NULL_NODE {
+            index = self.balance(index);
+
+            let [child_0, child_1] = self.nodes[index].children;
+            self.nodes[index].height = 1 + self.nodes[child_0].height.max(self.nodes[child_1].height);
+            self.nodes[index].bounds = self.nodes[child_0].bounds.union(&self.nodes[child_1].bounds);
+
+            index = self.nodes[index].parent;
+        }
+    }
+
+    // If one child of a is more than one level taller than the other, the
+    // taller child is rotated up into a's place. Returns whichever node is
+    // now where a was.
+    fn balance(&mut self, a: usize) -> usize {
+        if self.nodes[a].is_leaf() || self.nodes[a].height < 2 {
+            return a;
+        }
+
+        let [b, c] = self.nodes[a].children;
+        let difference = self.nodes[c].height - self.nodes[b].height;
+
+        if difference > 1 {
+            self.rotate_up(a, c, 1)
+        } else if difference < -1 {
+            self.rotate_up(a, b, 0)
+        } else {
+            a
+        }
+    }
+
+    // Swaps child (at a's side `side`) with a, keeping the child's taller
+    // grandchild and handing the shorter one to a
+    fn rotate_up(&mut self, a: usize, child: usize, side: usize) -> usize {
+        let [f, g] = self.nodes[child].children;
+        let a_parent = self.nodes[a].parent;
+
+        self.nodes[child].children[0] = a;
+        self.nodes[child].parent = a_parent;
+        self.nodes[a].parent = child;
+
+        if a_parent == NULL_NODE {
+            self.root = child;
+        } else if self.nodes[a_parent].children[0] == a {
+            self.nodes[a_parent].children[0] = child;
+        } else {
+            self.nodes[a_parent].children[1] = child;
+        }
+
+        let (keep, give) = if self.nodes[f].height > self.nodes[g].height { (f, g) } else { (g, f) };
+
+        self.nodes[child].children[1] = keep;
+        self.nodes[a].children[side] = give;
+        self.nodes[give].parent = a;
+
+        let [a_0, a_1] = self.nodes[a].children;
+        self.nodes[a].bounds = self.nodes[a_0].bounds.union(&self.nodes[a_1].bounds);
+        self.nodes[a].height = 1 + self.nodes[a_0].height.max(self.nodes[a_1].height);
+
+        self.nodes[child].bounds = self.nodes[a].bounds.union(&self.nodes[keep].bounds);
+        self.nodes[child].height = 1 + self.nodes[a].height.max(self.nodes[keep].height);
+
+        child
     }
 }
 
 // from https://gamedevelopment.tutsplus.com/tutorials/how-to-create-a-custom-2d-physics-engine-the-basics-and-impulse-resolution--gamedev-6331
 
 pub fn collision_manifold(a: &Entity, b: &Entity) -> Option<Manifold> {
-    match (a.physics.collision_shape(), b.physics.collision_shape()) {
+    if !a.physics.collides_with(&b.physics) {
+        return None;
+    }
+
+    shape_collision_manifold(&a.physics.collision_shape(), &b.physics.collision_shape())
+}
+
+// How two shapes overlap, whatever they belong to
+pub fn shape_collision_manifold(a: &CollisionShape, b: &CollisionShape) -> Option<Manifold> {
+    match (*a, *b) {
         (CollisionShape::AABB(abox), CollisionShape::AABB(bbox)) => aabb_aabb_collision_manifold(&abox, &bbox),
         (CollisionShape::Circle(acirc), CollisionShape::Circle(bcirc)) => circle_circle_collision_manifold(&acirc, &bcirc),
         (CollisionShape::AABB(abox), CollisionShape::Circle(bcirc)) => aabb_circle_collision_manifold(&abox, &bcirc),
@@ -30,17 +492,169 @@ pub fn collision_manifold(a: &Entity, b: &Entity) -> Option<Manifold> {
             aabb_circle_collision_manifold(&bbox, &acirc).as_mut().map(|m| {
                 m.normal *= -1.0;
                 *m
-            })
+            }),
+        (ashape, bshape) => sat_collision_manifold(&ashape, &bshape)
     }
 }
 
+// Every shape is the convex hull of a few points grown by a radius, which is
+// all the separating axis test needs to know about it
+#[derive(Debug, Copy, Clone)]
+struct RoundedHull {
+    points: [Vec2; MAX_POLYGON_VERTICES],
+    point_count: usize,
+    radius: f32
+}
+
+impl RoundedHull {
+    fn new(points: &[Vec2], radius: f32) -> RoundedHull {
+        let mut hull = RoundedHull { points: [Vec2::new(0.0, 0.0); MAX_POLYGON_VERTICES], point_count: points.len(), radius };
+        hull.points[..points.len()].copy_from_slice(points);
+        hull
+    }
+
+    fn from_shape(shape: &CollisionShape) -> RoundedHull {
+        match *shape {
+            // Straight from the corners, boxes flat as a line or a point are
+            // still fine to test against even if they're no polygon
+            CollisionShape::AABB(ref aabb) =>
+                RoundedHull::new(&[aabb.min, Vec2::new(aabb.max.x, aabb.min.y), aabb.max, Vec2::new(aabb.min.x, aabb.max.y)], 0.0),
+            CollisionShape::Circle(ref circle) => RoundedHull::new(&[circle.position], circle.radius),
+            CollisionShape::Polygon(ref polygon) => RoundedHull::new(polygon.vertices(), 0.0),
+            CollisionShape::Capsule(ref capsule) => RoundedHull::new(&[capsule.start, capsule.end], capsule.radius)
+        }
+    }
+
+    fn points(&self) -> &[Vec2] {
+        &self.points[..self.point_count]
+    }
+
+    fn project(&self, axis: Vec2) -> (f32, f32) {
+        let points = self.points();
+        if points.is_empty() {
+            return (-self.radius, self.radius);
+        }
+
+        let mut min = points[0].dot_product(axis);
+        let mut max = min;
+
+        for point in &points[1..] {
+            let p = point.dot_product(axis);
+            min = min.min(p);
+            max = max.max(p);
+        }
+
+        (min - self.radius, max + self.radius)
+    }
+
+    // The points furthest along the direction, one for a corner and more
+    // for a face. A direction that isn't a number picks out nothing, so
+    // that gets all of them.
+    fn support(&self, direction: Vec2) -> Vec<Vec2> {
+        let furthest = self.project(direction).1 - self.radius;
+        let support = self.points().iter()
+            .cloned()
+            .filter(|point| point.dot_product(direction) >= furthest - CONTACT_TOLERANCE)
+            .collect::<Vec<Vec2>>();
+
+        if support.is_empty() { self.points().to_vec() } else { support }
+    }
+}
+
+// Normals of the flat sides, the axes that can separate a shape from things
+// touching its faces
+fn face_normals(shape: &CollisionShape) -> Vec<Vec2> {
+    match *shape {
+        CollisionShape::AABB(_) => vec![Vec2::new(1.0, 0.0), Vec2::new(0.0, 1.0)],
+        CollisionShape::Circle(_) => vec![],
+        CollisionShape::Polygon(ref polygon) => polygon.normals(),
+        CollisionShape::Capsule(ref capsule) => {
+            let d = capsule.end - capsule.start;
+            if d.magnitude_squared() > 0.0 { vec![d.perpendicular().normalize()] } else { vec![] }
+        }
+    }
+}
+
+// Separating axis test. Convex shapes overlap unless they can be told apart
+// by projecting onto one of their face normals, or for rounded shapes the
+// line between a corner and the center of a rounded end, and the axis they
+// overlap least on is the way to push them apart.
+fn sat_collision_manifold(a: &CollisionShape, b: &CollisionShape) -> Option<Manifold> {
+    let a_hull = RoundedHull::from_shape(a);
+    let b_hull = RoundedHull::from_shape(b);
+
+    let mut axes = face_normals(a);
+    axes.extend(face_normals(b));
+
+    if a_hull.radius > 0.0 || b_hull.radius > 0.0 {
+        for &a_point in a_hull.points() {
+            for &b_point in b_hull.points() {
+                let between = b_point - a_point;
+                if between.magnitude_squared() > 0.0 {
+                    axes.push(between.normalize());
+                }
+            }
+        }
+    }
+
+    let mut manifold: Option<(f32, Vec2)> = None;
+
+    for axis in axes {
+        let (a_min, a_max) = a_hull.project(axis);
+        let (b_min, b_max) = b_hull.project(axis);
+
+        // How far b would have to move along the axis either way to clear a
+        let forwards = a_max - b_min;
+        let backwards = b_max - a_min;
+        let overlap = forwards.min(backwards);
+
+        if overlap <= 0.0 {
+            return None;
+        }
+
+        if manifold.is_none_or(|(penetration, _)| overlap < penetration) {
+            // Normals point from a towards b
+            let normal = if forwards < backwards { axis } else { axis * -1.0 };
+            manifold = Some((overlap, normal));
+        }
+    }
+
+    // Two circles on the same spot leave nothing to test
+    let (penetration, normal) = manifold.unwrap_or((a_hull.radius + b_hull.radius, Vec2::new(1.0, 0.0)));
+    Some(hull_manifold(&a_hull, &b_hull, penetration, normal))
+}
+
+// Fills in where the shapes touch. A corner pushing into the other shape
+// touches at the corner, two faces pressed together touch in the middle of
+// where they overlap.
+fn hull_manifold(a: &RoundedHull, b: &RoundedHull, penetration: f32, normal: Vec2) -> Manifold {
+    let a_support = a.support(normal);
+    let b_support = b.support(normal * -1.0);
+
+    let a_deepest = a.project(normal).1;
+    let b_deepest = b.project(normal).0;
+    let middle = (a_deepest + b_deepest) / 2.0;
+
+    let point = if a_support.len() == 1 {
+        a_support[0]
+    } else if b_support.len() == 1 {
+        b_support[0]
+    } else if a_support.is_empty() || b_support.is_empty() {
+        Vec2::new(0.0, 0.0)
+    } else {
+        let tangent = normal.perpendicular();
+        let (a_min, a_max) = RoundedHull::new(&a_support, 0.0).project(tangent);
+        let (b_min, b_max) = RoundedHull::new(&b_support, 0.0).project(tangent);
+        tangent * ((a_min.max(b_min) + a_max.min(b_max)) / 2.0)
+    };
+
+    let contact = point + normal * (middle - point.dot_product(normal));
+    Manifold::new(penetration, normal, contact)
+}
+
 fn aabb_aabb_collision_manifold(a: &AABB, b: &AABB) -> Option<Manifold> {
     let n = b.position() - a.position();
 
-    //println!("Normal: {:?}", n);
-    //println!("A box: {:?}", a);
-    //println!("B box: {:?}", b);
-
     let a_extent = a.half_extent();
     let b_extent = b.half_extent();
 
@@ -50,17 +664,19 @@ fn aabb_aabb_collision_manifold(a: &AABB, b: &AABB) -> Option<Manifold> {
         let y_overlap = a_extent.y + b_extent.y - n.y.abs();
 
         if y_overlap > 0.0 {
+            let (a_hull, b_hull) = (RoundedHull::from_shape(&CollisionShape::AABB(*a)), RoundedHull::from_shape(&CollisionShape::AABB(*b)));
+
             if x_overlap < y_overlap {
                 let normal = if n.x < 0.0 { Vec2::new(-1.0, 0.0) } else { Vec2::new(1.0, 0.0) };
-                return Some(Manifold { penetration: x_overlap, normal: normal });               
+                return Some(hull_manifold(&a_hull, &b_hull, x_overlap, normal));
             } else {
                 let normal = if n.y < 0.0 { Vec2::new(0.0, -1.0) } else { Vec2::new(0.0, 1.0) };
-                return Some(Manifold { penetration: y_overlap, normal: normal });               
+                return Some(hull_manifold(&a_hull, &b_hull, y_overlap, normal));
             }
         }
     }
 
-    return None;
+    None
 }
 
 fn circle_circle_collision_manifold(a: &Circle, b: &Circle) -> Option<Manifold> {
@@ -75,13 +691,13 @@ fn circle_circle_collision_manifold(a: &Circle, b: &Circle) -> Option<Manifold>
 
     distance = distance.sqrt();
 
-    //println!("n: {:?}, r: {}, d: {}", n, r, distance);
+    let (a_hull, b_hull) = (RoundedHull::new(&[a.position], a.radius), RoundedHull::new(&[b.position], b.radius));
 
     if distance != 0.0 {
-        Some(Manifold::new(r - distance, n / distance))
+        Some(hull_manifold(&a_hull, &b_hull, r - distance, n / distance))
     } else {
         // Circles are in same position
-        Some(Manifold::new(a.radius, Vec2::new(1.0, 0.0)))
+        Some(hull_manifold(&a_hull, &b_hull, a.radius, Vec2::new(1.0, 0.0)))
     }
 }
 
@@ -102,8 +718,8 @@ fn aabb_circle_collision_manifold(a: &AABB, b: &Circle) -> Option<Manifold> {
     if closest == n {
         inside = true;
 
-        // Find closest axis
-        if n.x.abs() > n.y.abs() {
+        // Find the axis with the nearest edge
+        if a_extent.x - n.x.abs() < a_extent.y - n.y.abs() {
             // Clamp to closest extent
             if closest.x > 0.0 {
                 closest.x = a_extent.x
@@ -131,92 +747,475 @@ fn aabb_circle_collision_manifold(a: &AABB, b: &Circle) -> Option<Manifold> {
 
     distance = distance.sqrt();
 
-    //println!("Normal: {:?}, n: {:?}, distance: {}", normal, n, distance);
-
     // Collision normal needs to be flipped to point outside if circle was
     // inside the AABB
-    if inside {
-        Some(Manifold::new(b.radius - distance, (normal * -1.0) / distance)) 
+    let (a_hull, b_hull) = (RoundedHull::from_shape(&CollisionShape::AABB(*a)), RoundedHull::new(&[b.position], b.radius));
+
+    if distance == 0.0 {
+        // The center is right on an edge, push it out the way the edge faces
+        let normal = if closest.x.abs() == a_extent.x {
+            Vec2::new(closest.x.signum(), 0.0)
+        } else {
+            Vec2::new(0.0, closest.y.signum())
+        };
+        Some(hull_manifold(&a_hull, &b_hull, b.radius, normal))
+    } else if inside {
+        // The center has to come back out past the edge as well
+        Some(hull_manifold(&a_hull, &b_hull, b.radius + distance, (normal * -1.0) / distance))
     } else {
-        Some(Manifold::new(b.radius - distance, normal / distance)) 
+        Some(hull_manifold(&a_hull, &b_hull, b.radius - distance, normal / distance))
     }
 }
 
-pub fn resolve_collision(a: &mut Entity, b: &mut Entity, manifold: Manifold) {
-    resolve_bounce(&mut a.physics, &mut b.physics, manifold);
-    fixup_position(&mut a.physics, &mut b.physics, manifold);
-}
+pub fn nearest_ray_intersection<'a, I, F>(ray: &Ray, entities: I, filter: F) -> Option<(EntityId, Vec2)>
+    where I: IntoIterator<Item=(EntityId, &'a Entity)>, F: Fn(EntityId, &Entity) -> bool {
+
+    let mut intersection = None;
+    let mut min_distance = f32::INFINITY;
 
-fn resolve_bounce(a: &mut Physics, b: &mut Physics, manifold: Manifold) {
-    let relative_velocity = b.velocity - a.velocity;
-    let velocity_along_normal = relative_velocity.dot_product(manifold.normal);
+    for (id, entity) in entities {
+        if !filter(id, entity) {
+            continue;
+        }
 
-    //println!("Velocity along normal: {}", velocity_along_normal);
+        let maybe_point = ray.shape_intersection(&entity.physics.collision_shape());
 
-    if velocity_along_normal > 0.0 {
-        // Do not resolve if velocities are separating
-        return
+        if let Some(point) = maybe_point {
+            let distance = ray.origin.distance(point);
+            if distance < min_distance {
+                intersection = Some((id, point));
+                min_distance = distance;
+            }
+        };
     }
 
-    let e = a.restitution.min(b.restitution);
-    let j = (-(1.0 + e) * velocity_along_normal) / (a.inv_mass + b.inv_mass);
-    
-    let impulse = j * manifold.normal;
+    intersection
+}
 
-    //println!("Impulse: {:?}", impulse);
-    //println!("Velocity before delta: {:?}, {:?}", a.velocity, b.velocity);
+// When a shape moving from one place to another first touches something,
+// as a fraction of the move, and which way it was pushed back
+#[derive(Debug, Copy, Clone)]
+pub struct TimeOfImpact {
+    pub time: f32,
+    // Points from the thing hit towards the moving shape
+    pub normal: Vec2
+}
 
-    a.velocity -= a.inv_mass * impulse;
-    b.velocity += b.inv_mass * impulse;
+impl TimeOfImpact {
+    pub fn new(time: f32, normal: Vec2) -> TimeOfImpact {
+        TimeOfImpact { time, normal }
+    }
+}
 
-    //println!("Velocity after delta: {:?}, {:?}", a.velocity, b.velocity);
+// Circles that already touch the shape hit it at time 0
+pub fn swept_circle_shape(from: Vec2, radius: f32, motion: Vec2, shape: &CollisionShape) -> Option<TimeOfImpact> {
+    match *shape {
+        CollisionShape::AABB(ref aabb) => swept_circle_aabb(from, radius, motion, aabb),
+        CollisionShape::Circle(ref circle) => swept_circle_circle(from, radius, motion, circle),
+        CollisionShape::Polygon(ref polygon) => swept_circle_polygon(from, radius, motion, polygon),
+        CollisionShape::Capsule(ref capsule) => swept_circle_capsule(from, radius, motion, capsule)
+    }
 }
 
-fn fixup_position(a: &mut Physics, b: &mut Physics, manifold: Manifold) {
+// Shapes first touch where a corner or rounded end of one touches the
+// other, so each of the moving shape's points is swept as a circle at the
+// still one, and each of the still shape's points the opposite way back
+// at the moving one. Shapes that already touch hit at time 0.
+pub fn swept_shape(shape: &CollisionShape, motion: Vec2, other: &CollisionShape) -> Option<TimeOfImpact> {
+    if let Some(manifold) = shape_collision_manifold(other, shape) {
+        return Some(TimeOfImpact::new(0.0, manifold.normal));
+    }
+
+    let moving = RoundedHull::from_shape(shape);
+    let still = RoundedHull::from_shape(other);
+
+    let mut nearest: Option<TimeOfImpact> = None;
 
-    let correction = manifold.penetration / (a.inv_mass + b.inv_mass) * manifold.normal;
+    for &point in moving.points() {
+        if let Some(hit) = swept_circle_shape(point, moving.radius, motion, other) {
+            if nearest.is_none_or(|nearest| hit.time < nearest.time) {
+                nearest = Some(hit);
+            }
+        }
+    }
 
-    a.position -= a.inv_mass * correction;
-    b.position += b.inv_mass * correction;
+    for &point in still.points() {
+        if let Some(hit) = swept_circle_shape(point, still.radius, motion * -1.0, shape) {
+            if nearest.is_none_or(|nearest| hit.time < nearest.time) {
+                nearest = Some(TimeOfImpact::new(hit.time, hit.normal * -1.0));
+            }
+        }
+    }
+
+    nearest
 }
 
-pub fn nearest_ray_intersection(ray: &Ray, entities: &[Entity]) -> Option<(usize, Vec2)> {
-    let mut intersection = None;
-    let mut min_distance = f32::INFINITY;
+// Same as a ray against a circle with both radii
+pub fn swept_circle_circle(from: Vec2, radius: f32, motion: Vec2, circle: &Circle) -> Option<TimeOfImpact> {
+    let f = from - circle.position;
+    let r = radius + circle.radius;
+
+    let c = f.dot_product(f) - r * r;
+    if c <= 0.0 {
+        return Some(TimeOfImpact::new(0.0, direction_or_x(f)));
+    }
+
+    let a = motion.dot_product(motion);
+    let b = 2.0 * f.dot_product(motion);
+    let discriminant = b * b - 4.0 * a * c;
+
+    if a == 0.0 || discriminant < 0.0 {
+        return None;
+    }
 
-    for (i, entity) in entities.iter().enumerate() {
-        let maybe_point = match entity.physics.collision_shape() {
-            CollisionShape::AABB(aabb) => ray.box_intersection(&aabb),
-            CollisionShape::Circle(circle) => ray.circle_intersection(&circle)
+    // Starting outside, so the nearer root is where it first touches
+    let time = (-b - discriminant.sqrt()) / (2.0 * a);
+    if !(0.0..=1.0).contains(&time) {
+        return None;
+    }
+
+    Some(TimeOfImpact::new(time, direction_or_x(from + motion * time - circle.position)))
+}
+
+// A circle touches a box when its center is inside the box grown by the
+// radius with rounded corners. That's the box grown sideways, the box grown
+// up and down and a circle on each corner, and the first of those the
+// center reaches is where it hits.
+pub fn swept_circle_aabb(from: Vec2, radius: f32, motion: Vec2, aabb: &AABB) -> Option<TimeOfImpact> {
+    let closest = Vec2::new(from.x.max(aabb.min.x).min(aabb.max.x), from.y.max(aabb.min.y).min(aabb.max.y));
+    if from.distance(closest) <= radius {
+        let normal = if closest == from {
+            // Center inside the box, push out the nearest side
+            aabb_exit_normal(from, aabb)
+        } else {
+            direction_or_x(from - closest)
         };
+        return Some(TimeOfImpact::new(0.0, normal));
+    }
+
+    let wide = AABB::new(aabb.min - Vec2::new(radius, 0.0), aabb.max + Vec2::new(radius, 0.0));
+    let tall = AABB::new(aabb.min - Vec2::new(0.0, radius), aabb.max + Vec2::new(0.0, radius));
+
+    let corners = [
+        aabb.min,
+        Vec2::new(aabb.max.x, aabb.min.y),
+        aabb.max,
+        Vec2::new(aabb.min.x, aabb.max.y)
+    ];
+
+    let mut nearest = swept_point_aabb(from, motion, &wide);
+
+    for hit in swept_point_aabb(from, motion, &tall).into_iter()
+        .chain(corners.iter().filter_map(|&corner| swept_circle_circle(from, radius, motion, &Circle::new(corner, 0.0)))) {
+
+        if nearest.is_none_or(|nearest| hit.time < nearest.time) {
+            nearest = Some(hit);
+        }
+    }
+
+    nearest
+}
+
+// Like boxes, the circle's center hits the polygon grown by the radius:
+// every edge pushed out along its normal and a circle on every corner
+pub fn swept_circle_polygon(from: Vec2, radius: f32, motion: Vec2, polygon: &Polygon) -> Option<TimeOfImpact> {
+    if let Some(manifold) = sat_collision_manifold(&CollisionShape::Polygon(*polygon), &CollisionShape::Circle(Circle::new(from, radius))) {
+        return Some(TimeOfImpact::new(0.0, manifold.normal));
+    }
+
+    let mut nearest: Option<TimeOfImpact> = None;
+    let path = LineSegment::new(from, from + motion);
+
+    for (edge, normal) in polygon.edges().iter().zip(polygon.normals()) {
+        // Only edges it's moving towards can be hit from outside
+        if motion.dot_product(normal) >= 0.0 {
+            continue;
+        }
+
+        let offset = normal * radius;
+        let hit = path.intersect(&LineSegment::new(edge.start + offset, edge.end + offset))
+            .map(|crossing| TimeOfImpact::new(crossing.t(), normal));
+
+        if let Some(hit) = hit {
+            if nearest.is_none_or(|nearest| hit.time < nearest.time) {
+                nearest = Some(hit);
+            }
+        }
+    }
+
+    for &vertex in polygon.vertices() {
+        if let Some(hit) = swept_circle_circle(from, radius, motion, &Circle::new(vertex, 0.0)) {
+            if nearest.is_none_or(|nearest| hit.time < nearest.time) {
+                nearest = Some(hit);
+            }
+        }
+    }
+
+    nearest
+}
+
+// The two sides are the segment pushed out either way by both radii, the
+// ends are circles
+pub fn swept_circle_capsule(from: Vec2, radius: f32, motion: Vec2, capsule: &Capsule) -> Option<TimeOfImpact> {
+    if let Some(manifold) = sat_collision_manifold(&CollisionShape::Capsule(*capsule), &CollisionShape::Circle(Circle::new(from, radius))) {
+        return Some(TimeOfImpact::new(0.0, manifold.normal));
+    }
+
+    let mut nearest: Option<TimeOfImpact> = None;
+    let path = LineSegment::new(from, from + motion);
 
-        match maybe_point {
-            Some(point) => {
-                //println!("Intersection point: {:?}", point);
-                let distance = ray.origin.distance(point);
-                if distance < min_distance {
-                    intersection = Some((i, point));
-                    min_distance = distance;
+    let d = capsule.end - capsule.start;
+    if d.magnitude_squared() > 0.0 {
+        let side = d.perpendicular().normalize();
+
+        for &normal in &[side, side * -1.0] {
+            if motion.dot_product(normal) >= 0.0 {
+                continue;
+            }
+
+            let offset = normal * (radius + capsule.radius);
+            let hit = path.intersect(&LineSegment::new(capsule.start + offset, capsule.end + offset))
+                .map(|crossing| TimeOfImpact::new(crossing.t(), normal));
+
+            if let Some(hit) = hit {
+                if nearest.is_none_or(|nearest| hit.time < nearest.time) {
+                    nearest = Some(hit);
                 }
             }
-            None => {}
-        };
+        }
     }
-    
-    //println!("Intersection: {:?}", intersection);
-    return intersection;
+
+    for &end in &[capsule.start, capsule.end] {
+        if let Some(hit) = swept_circle_circle(from, radius, motion, &Circle::new(end, capsule.radius)) {
+            if nearest.is_none_or(|nearest| hit.time < nearest.time) {
+                nearest = Some(hit);
+            }
+        }
+    }
+
+    nearest
 }
 
-pub fn collision_point(entity: &Entity, entities: &[Entity]) -> Option<(usize, Vec2)> {
-    let movement_line = LineSegment::new(entity.physics.position - entity.physics.velocity, entity.physics.position);
-    
-    nearest_ray_intersection(&Ray::from_segment(&movement_line), entities).and_then(|result| {
-        if movement_line.has_point(result.1) {
-            Some(result)
-        } else {
-            None
+// Where a point moving from outside the box first crosses into it
+fn swept_point_aabb(from: Vec2, motion: Vec2, aabb: &AABB) -> Option<TimeOfImpact> {
+    let mut entry = 0.0f32;
+    let mut exit = 1.0f32;
+    let mut normal = Vec2::new(0.0, 0.0);
+
+    let axes = [
+        (from.x, motion.x, aabb.min.x, aabb.max.x, Vec2::new(1.0, 0.0)),
+        (from.y, motion.y, aabb.min.y, aabb.max.y, Vec2::new(0.0, 1.0))
+    ];
+
+    for &(start, delta, min, max, axis) in &axes {
+        if delta == 0.0 {
+            if start < min || start > max {
+                return None;
+            }
+            continue;
         }
-    })
 
+        let t1 = (min - start) / delta;
+        let t2 = (max - start) / delta;
+        let (near, far) = if t1 < t2 { (t1, t2) } else { (t2, t1) };
+
+        if near > entry {
+            entry = near;
+            // Moving right means it came in through the left side
+            normal = if delta > 0.0 { axis * -1.0 } else { axis };
+        }
+        exit = exit.min(far);
+
+        if entry > exit {
+            return None;
+        }
+    }
+
+    Some(TimeOfImpact::new(entry, normal))
 }
 
+fn aabb_exit_normal(point: Vec2, aabb: &AABB) -> Vec2 {
+    let distances = [
+        (point.x - aabb.min.x, Vec2::new(-1.0, 0.0)),
+        (aabb.max.x - point.x, Vec2::new(1.0, 0.0)),
+        (point.y - aabb.min.y, Vec2::new(0.0, -1.0)),
+        (aabb.max.y - point.y, Vec2::new(0.0, 1.0))
+    ];
+
+    let mut nearest = distances[0];
+    for &side in &distances[1..] {
+        if side.0 < nearest.0 {
+            nearest = side;
+        }
+    }
+    nearest.1
+}
+
+fn direction_or_x(v: Vec2) -> Vec2 {
+    if v.magnitude_squared() == 0.0 {
+        Vec2::new(1.0, 0.0)
+    } else {
+        v.normalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entity::{Level, Physics};
+    use event::GameEvent;
+    use level_file::read_level;
+    use material::Material;
+    use rng::Rng;
+    use shape::Shape;
+    use tunables::Tunables;
+
+    #[test]
+    fn circle_centered_on_box_edge_is_pushed_out() {
+        let aabb = AABB::new(Vec2::new(100.0, 80.0), Vec2::new(140.0, 120.0));
+        let circle = Circle::new(Vec2::new(100.0, 100.0), 10.0);
+
+        let manifold = aabb_circle_collision_manifold(&aabb, &circle).unwrap();
+
+        assert_eq!(manifold.normal, Vec2::new(-1.0, 0.0));
+        assert_eq!(manifold.penetration, 10.0);
+        assert!(manifold.contact.x.is_finite() && manifold.contact.y.is_finite());
+    }
+
+    #[test]
+    fn flat_boxes_collide_without_panicking() {
+        let point = CollisionShape::AABB(AABB::new(Vec2::new(5.0, 5.0), Vec2::new(5.0, 5.0)));
+        let line = CollisionShape::AABB(AABB::new(Vec2::new(0.0, 5.0), Vec2::new(10.0, 5.0)));
+        let polygon = CollisionShape::Polygon(Polygon::new(&[Vec2::new(0.0, 0.0), Vec2::new(10.0, 0.0), Vec2::new(5.0, 10.0)]).unwrap());
+
+        assert!(shape_collision_manifold(&point, &polygon).is_some());
+        assert!(shape_collision_manifold(&line, &polygon).is_some());
+        assert!(shape_collision_manifold(&line, &CollisionShape::Circle(Circle::new(Vec2::new(20.0, 20.0), 1.0))).is_none());
+    }
+
+    #[test]
+    fn turned_flat_boxes_collide_without_panicking() {
+        let mut line = Physics::new(Shape::Rect { extent: Vec2::new(40.0, 0.0) }, Vec2::new(100.0, 100.0), Material::Stone, 0.0);
+        line.angle = f32::consts::FRAC_PI_2;
+        let circle = CollisionShape::Circle(Circle::new(Vec2::new(100.0, 115.0), 5.0));
+
+        let bounds = line.bounds();
+        assert!((bounds.min.y - 80.0).abs() < 0.001 && (bounds.max.y - 120.0).abs() < 0.001);
+        assert!(shape_collision_manifold(&line.collision_shape(), &circle).is_some());
+
+        line.shape = Shape::Rect { extent: Vec2::new(0.0, 0.0) };
+        assert!(shape_collision_manifold(&line.collision_shape(), &circle).is_none());
+    }
+
+    #[test]
+    fn overlap_query_with_a_point_box_does_not_panic() {
+        let mut level = Level::new(800.0, 600.0, 1, Tunables::new());
+        level.step(&[], 10);
+
+        let center = level.player().physics.position;
+        let hits = level.overlap_aabb(&AABB::new(center, center), |_, _| true);
+        assert_eq!(hits, vec![level.player_id.unwrap()]);
+
+        let across = level.overlap_aabb(&AABB::new(Vec2::new(0.0, 300.0), Vec2::new(800.0, 300.0)), |_, _| true);
+        assert_eq!(across.len(), 3);
+    }
+
+    #[test]
+    fn player_on_wall_edge_does_not_panic() {
+        let lines = ["size 800 600", "player 100 100", "wall 40 40 120 100"];
+        let mut level = read_level(lines.iter().cloned(), 1, Tunables::new()).unwrap();
+
+        for _ in 0..10 {
+            level.step(&[], 10);
+        }
+
+        let position = level.player().physics.position;
+        assert!(position.x.is_finite() && position.y.is_finite());
+        assert!(position.x <= 100.0 - Tunables::new().player_radius + 1.0);
+    }
+
+    #[test]
+    fn doors_set_off_sensors() {
+        let text = "size 800 600\nplayer 100 100\nsensor 40 40 400 400\ndoor 40 40 400 200 400 400 400";
+        let mut level = read_level(text.lines(), 1, Tunables::new()).unwrap();
+
+        let door = level.iter().find(|&(_, entity)| entity.mover.is_some()).unwrap().0;
+        level.get_mut(door).unwrap().mover.as_mut().unwrap().open();
+
+        let mut entered = false;
+        for _ in 0..100 {
+            level.step(&[], 10);
+            entered |= level.events.iter().any(|event| match *event {
+                GameEvent::SensorEnter { other, .. } => other == door,
+                _ => false
+            });
+        }
+
+        assert!(entered);
+    }
+
+    fn random_box(rng: &mut Rng, max_size: f32) -> AABB {
+        let min = Vec2::new(rng.range(0.0, 1000.0), rng.range(0.0, 1000.0));
+        AABB::new(min, min + Vec2::new(rng.range(1.0, max_size), rng.range(1.0, max_size)))
+    }
+
+    #[test]
+    fn tree_queries_match_checking_every_box() {
+        let mut rng = Rng::new(3);
+        let mut tree = DynamicTree::new();
+        let mut boxes = vec![];
+
+        for i in 0..300 {
+            let bounds = random_box(&mut rng, 60.0);
+            boxes.push(Some((tree.insert(bounds, i), bounds)));
+        }
+
+        // Churn it the way a level does, moving some and removing others
+        for (i, slot) in boxes.iter_mut().enumerate() {
+            let (proxy, _) = slot.unwrap();
+            match i % 3 {
+                0 => {
+                    tree.remove(proxy);
+                    *slot = None;
+                }
+                1 => {
+                    let bounds = random_box(&mut rng, 60.0);
+                    tree.update(proxy, bounds);
+                    *slot = Some((proxy, bounds));
+                }
+                _ => {}
+            }
+        }
+
+        let live = |i: usize| boxes[i].map(|(_, bounds)| bounds);
+
+        for _ in 0..200 {
+            let query = random_box(&mut rng, 200.0);
+
+            let mut found = vec![];
+            tree.query(&query, |i| {
+                if live(i).unwrap().overlaps(&query) {
+                    found.push(i);
+                }
+            });
+            found.sort();
+
+            let expected = (0..300).filter(|&i| live(i).is_some_and(|bounds| bounds.overlaps(&query))).collect::<Vec<usize>>();
+            assert_eq!(found, expected);
+
+            let ray = Ray::new(Vec2::new(rng.range(0.0, 1000.0), rng.range(0.0, 1000.0)), Vec2::new(rng.range(-1.0, 1.0), rng.range(-1.0, 1.0)));
+            let hit = tree.ray_cast(&ray, |i| ray.box_entry_distance(&live(i).unwrap()).map(|distance| (distance, distance)));
+
+            // Ties go to the smallest index, like the tree
+            let mut nearest: Option<(usize, f32)> = None;
+            for i in 0..300 {
+                if let Some(distance) = live(i).and_then(|bounds| ray.box_entry_distance(&bounds)) {
+                    if nearest.is_none_or(|(_, nearest_distance)| distance < nearest_distance) {
+                        nearest = Some((i, distance));
+                    }
+                }
+            }
+            assert_eq!(hit, nearest);
+        }
+    }
+}
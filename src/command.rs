@@ -0,0 +1,19 @@
+
+use vec2::Vec2;
+
+// Everything a player can ask of the simulation during a single step. The
+// binary builds these from the input state each tick, but bots and tools can just as
+// well build them by hand.
+#[derive(Debug, Copy, Clone)]
+pub enum Command {
+    // Sets the player's acceleration for this tick
+    Move(Vec2),
+    StartGunCharging,
+    FireGun(Vec2),
+    // Drops the charge without firing, for when the game stops listening
+    // with the fire button down
+    CancelGunCharging,
+    // Fires a PewPew straight away without touching the gun's charge
+    QuickFire(Vec2),
+    LookAt(Vec2)
+}
@@ -0,0 +1,93 @@
+
+use sdl2::pixels::Color;
+
+// Small components that don't need a module of their own. Anything bigger
+// (Gun, Shield, Ai, Bullet, Player) lives next to the code that uses it.
+
+// Takes part in entity collisions. Entities without one are moved but
+// never pushed around.
+#[derive(Debug, Copy, Clone)]
+pub struct Collider;
+
+#[derive(Debug, Copy, Clone)]
+pub struct Health {
+    pub health: f32,
+    // What it started out with
+    pub max: f32
+}
+
+impl Health {
+    pub fn new(health: f32) -> Health {
+        Health { health, max: health }
+    }
+
+    // How much is left, from 1 down to 0
+    pub fn fraction(&self) -> f32 {
+        self.health / self.max
+    }
+
+    pub fn take_damage(&mut self, amount: f32) {
+        self.health = (self.health - amount).max(0.0);
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.health <= 0.0
+    }
+}
+
+// Hurts the player for as long as they touch, in health per second
+#[derive(Debug, Copy, Clone)]
+pub struct ContactDamage {
+    pub damage: f32
+}
+
+impl ContactDamage {
+    pub fn new(damage: f32) -> ContactDamage {
+        ContactDamage { damage }
+    }
+}
+
+// The entity is removed once it has been around for duration ms
+#[derive(Debug, Copy, Clone)]
+pub struct Lifetime {
+    pub time_running: u32,
+    pub duration: u32
+}
+
+impl Lifetime { 
+    pub fn new(duration: u32) -> Lifetime {
+        Lifetime { time_running: 0, duration }
+    }
+
+    pub fn update(&mut self, time_delta: u32) {
+        self.time_running += time_delta;
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.time_running > self.duration
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum RenderStyle {
+    // The physics shape, filled in
+    Filled,
+    // A dot in the middle that shrinks along with the entity's health
+    Core { radius: f32 },
+    // A circle that grows one pixel every step_duration ms of its lifetime
+    Burst { step_duration: u32 }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Renderable {
+    pub color: Color,
+    pub style: RenderStyle,
+    // Higher layers are drawn on top
+    pub layer: u8
+}
+
+impl Renderable {
+    pub fn new(color: Color, style: RenderStyle, layer: u8) -> Renderable {
+        Renderable { color, style, layer }
+    }
+}
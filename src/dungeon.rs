@@ -0,0 +1,335 @@
+use std::f32;
+
+use vec2::Vec2;
+use entity::{Level, make_player, make_wall, make_enemy};
+use rng::Rng;
+use tunables::Tunables;
+
+// Dungeons are laid out on a grid of square tiles this many pixels across
+const TILE_SIZE: f32 = 20.0;
+
+// Areas aren't split into pieces narrower than this many tiles
+const MIN_AREA_TILES: u32 = 10;
+
+// Smallest room, with space to walk around an enemy
+const MIN_ROOM_TILES: u32 = 6;
+
+// Room for the smallest room, a tile of rock around it and the edge.
+// Windows smaller than this still get a dungeon this big.
+const MIN_GRID_TILES: u32 = MIN_ROOM_TILES + 4;
+
+// Wide enough for an enemy to get through
+const CORRIDOR_TILES: u32 = 3;
+
+// Enemies don't start any closer to the player than this
+const MIN_ENEMY_DISTANCE: f32 = 150.0;
+
+// Tries at finding a free spot for an enemy before putting it anywhere
+const ENEMY_PLACEMENT_TRIES: u32 = 20;
+
+// A rectangle of tiles
+#[derive(Debug, Copy, Clone)]
+struct Area {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32
+}
+
+impl Area {
+    fn new(x: u32, y: u32, width: u32, height: u32) -> Area {
+        Area { x, y, width, height }
+    }
+
+    fn center(&self) -> (u32, u32) {
+        (self.x + self.width / 2, self.y + self.height / 2)
+    }
+
+    // Middle of the area in pixels
+    fn position(&self) -> Vec2 {
+        Vec2::new(self.x as f32 + self.width as f32 / 2.0, self.y as f32 + self.height as f32 / 2.0) * TILE_SIZE
+    }
+}
+
+// Which tiles have been dug out. Everything starts out as rock.
+struct Grid {
+    width: u32,
+    height: u32,
+    floor: Vec<bool>
+}
+
+impl Grid {
+    fn new(width: u32, height: u32) -> Grid {
+        Grid { width, height, floor: vec![false; (width * height) as usize] }
+    }
+
+    fn is_floor(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height &&
+            self.floor[(y as u32 * self.width + x as u32) as usize]
+    }
+
+    // Rock with floor next to it, corners included. Rock further in can't
+    // be reached so it doesn't need walls.
+    fn is_wall(&self, x: u32, y: u32) -> bool {
+        let (x, y) = (x as i32, y as i32);
+        if self.is_floor(x, y) {
+            return false;
+        }
+
+        for dy in -1..2 {
+            for dx in -1..2 {
+                if self.is_floor(x + dx, y + dy) {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    // The tiles around the edge always stay rock
+    fn carve(&mut self, area: Area) {
+        for y in area.y.max(1)..(area.y + area.height).min(self.height - 1) {
+            for x in area.x.max(1)..(area.x + area.width).min(self.width - 1) {
+                self.floor[(y * self.width + x) as usize] = true;
+            }
+        }
+    }
+
+    // Straight along one axis, CORRIDOR_TILES wide around the line between
+    // the two tiles
+    fn carve_line(&mut self, from: (u32, u32), to: (u32, u32)) {
+        let half = CORRIDOR_TILES / 2;
+        let (x0, x1) = (from.0.min(to.0), from.0.max(to.0));
+        let (y0, y1) = (from.1.min(to.1), from.1.max(to.1));
+
+        self.carve(Area::new(x0.saturating_sub(half), y0.saturating_sub(half), x1 - x0 + 2 * half + 1, y1 - y0 + 2 * half + 1));
+    }
+}
+
+// Digs rooms and corridors out of the level's rock by cutting it in two,
+// then each half in two and so on until the pieces get small, with a room
+// in each piece. The rooms on either side of every cut get joined by a
+// corridor, so every room can be reached from every other. The player
+// starts in the middle of one room and the enemies are spread over the
+// others.
+pub fn generate(width: f32, height: f32, seed: u64, tunables: Tunables, enemy_count: u32) -> Level {
+    let columns = ((width / TILE_SIZE) as u32).max(MIN_GRID_TILES);
+    let rows = ((height / TILE_SIZE) as u32).max(MIN_GRID_TILES);
+
+    let mut level = Level::empty(width.max(columns as f32 * TILE_SIZE), height.max(rows as f32 * TILE_SIZE), seed, tunables);
+
+    let mut grid = Grid::new(columns, rows);
+    let rooms = split(&mut level.rng, &mut grid, Area::new(1, 1, columns - 2, rows - 2));
+
+    for block in wall_blocks(&grid) {
+        level.insert(make_wall(block.width as f32 * TILE_SIZE, block.height as f32 * TILE_SIZE, block.position()));
+    }
+
+    let start = level.rng.range_u32(0, rooms.len() as u32) as usize;
+    let player_position = rooms[start].position();
+    level.insert(make_player(&tunables, player_position));
+
+    let others = (0..rooms.len()).filter(|&i| i != start).collect::<Vec<usize>>();
+    let mut enemy_positions: Vec<Vec2> = vec![];
+
+    let is_clear = |position: Vec2, enemy_positions: &[Vec2]| {
+        position.distance(player_position) >= tunables.player_radius + tunables.enemy_radius &&
+            enemy_positions.iter().all(|other| position.distance(*other) >= tunables.enemy_radius * 2.0)
+    };
+
+    for _ in 0..enemy_count {
+        // Furthest from the player so far, for when nowhere is far enough
+        let mut best = None;
+        let mut best_distance = 0.0;
+
+        for _ in 0..ENEMY_PLACEMENT_TRIES {
+            let room = if others.is_empty() {
+                rooms[start]
+            } else {
+                rooms[others[level.rng.range_u32(0, others.len() as u32) as usize]]
+            };
+            let position = random_position(&mut level.rng, room, tunables.enemy_radius);
+
+            let distance = position.distance(player_position);
+
+            if is_clear(position, &enemy_positions) && distance > best_distance {
+                best = Some(position);
+                best_distance = distance;
+
+                if distance >= MIN_ENEMY_DISTANCE {
+                    break;
+                }
+            }
+        }
+
+        // Crowded small levels get fewer enemies rather than one on top of
+        // the player
+        if let Some(position) = best {
+            enemy_positions.push(position);
+            level.insert(make_enemy(&tunables, position));
+        }
+    }
+
+    level
+}
+
+// Returns the rooms carved in the area
+fn split(rng: &mut Rng, grid: &mut Grid, area: Area) -> Vec<Area> {
+    let can_cut_across = area.width >= 2 * MIN_AREA_TILES;
+    let can_cut_down = area.height >= 2 * MIN_AREA_TILES;
+
+    // Long areas are cut across the long way, so the pieces come out
+    // closer to square
+    let cut_across = match (can_cut_across, can_cut_down) {
+        (false, false) => {
+            let room = place_room(rng, area);
+            grid.carve(room);
+            return vec![room];
+        }
+        (true, false) => true,
+        (false, true) => false,
+        (true, true) => {
+            if area.width * 4 > area.height * 5 {
+                true
+            } else if area.height * 4 > area.width * 5 {
+                false
+            } else {
+                rng.next_f32() < 0.5
+            }
+        }
+    };
+
+    let (first, second) = if cut_across {
+        let at = rng.range_u32(MIN_AREA_TILES, area.width - MIN_AREA_TILES + 1);
+        (Area::new(area.x, area.y, at, area.height), Area::new(area.x + at, area.y, area.width - at, area.height))
+    } else {
+        let at = rng.range_u32(MIN_AREA_TILES, area.height - MIN_AREA_TILES + 1);
+        (Area::new(area.x, area.y, area.width, at), Area::new(area.x, area.y + at, area.width, area.height - at))
+    };
+
+    let mut rooms = split(rng, grid, first);
+    let second_rooms = split(rng, grid, second);
+
+    // Joining the nearest pair keeps corridors short and out of other rooms
+    let (from, to) = closest_rooms(&rooms, &second_rooms);
+
+    // Bends one way or the other
+    let corner = if rng.next_f32() < 0.5 { (to.0, from.1) } else { (from.0, to.1) };
+    grid.carve_line(from, corner);
+    grid.carve_line(corner, to);
+
+    rooms.extend(second_rooms);
+    rooms
+}
+
+// Somewhere in the area, with at least a tile of rock around it when
+// there's space so rooms in neighbouring areas don't run together
+fn place_room(rng: &mut Rng, area: Area) -> Area {
+    let margin_x = if area.width > 2 { 1 } else { 0 };
+    let margin_y = if area.height > 2 { 1 } else { 0 };
+
+    let max_width = area.width - 2 * margin_x;
+    let max_height = area.height - 2 * margin_y;
+
+    let width = rng.range_u32(MIN_ROOM_TILES.min(max_width), max_width + 1);
+    let height = rng.range_u32(MIN_ROOM_TILES.min(max_height), max_height + 1);
+
+    let x = area.x + margin_x + rng.range_u32(0, max_width - width + 1);
+    let y = area.y + margin_y + rng.range_u32(0, max_height - height + 1);
+
+    Area::new(x, y, width, height)
+}
+
+// Centers of the two rooms nearest each other, one from each side
+fn closest_rooms(first: &[Area], second: &[Area]) -> ((u32, u32), (u32, u32)) {
+    let mut best = (first[0].center(), second[0].center());
+    let mut best_distance = f32::MAX;
+
+    for a in first {
+        for b in second {
+            let distance = a.position().distance(b.position());
+            if distance < best_distance {
+                best = (a.center(), b.center());
+                best_distance = distance;
+            }
+        }
+    }
+
+    best
+}
+
+// Anywhere a circle of the given radius fits inside the room
+fn random_position(rng: &mut Rng, room: Area, radius: f32) -> Vec2 {
+    let min = Vec2::new(room.x as f32, room.y as f32) * TILE_SIZE + Vec2::new(radius, radius);
+    let max = Vec2::new((room.x + room.width) as f32, (room.y + room.height) as f32) * TILE_SIZE - Vec2::new(radius, radius);
+
+    Vec2::new(rng.range(min.x, max.x.max(min.x)), rng.range(min.y, max.y.max(min.y)))
+}
+
+// Wall tiles merged into as few boxes as it takes, going row by row and
+// growing each box right and then down as far as it goes
+fn wall_blocks(grid: &Grid) -> Vec<Area> {
+    let mut covered = vec![false; (grid.width * grid.height) as usize];
+    let mut blocks = vec![];
+
+    let is_free_wall = |covered: &Vec<bool>, x: u32, y: u32| grid.is_wall(x, y) && !covered[(y * grid.width + x) as usize];
+
+    for y in 0..grid.height {
+        for x in 0..grid.width {
+            if !is_free_wall(&covered, x, y) {
+                continue;
+            }
+
+            let mut width = 1;
+            while x + width < grid.width && is_free_wall(&covered, x + width, y) {
+                width += 1;
+            }
+
+            let mut height = 1;
+            while y + height < grid.height && (x..x + width).all(|column| is_free_wall(&covered, column, y + height)) {
+                height += 1;
+            }
+
+            for row in y..y + height {
+                for column in x..x + width {
+                    covered[(row * grid.width + column) as usize] = true;
+                }
+            }
+
+            blocks.push(Area::new(x, y, width, height));
+        }
+    }
+
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn players_start_clear_of_walls_and_enemies() {
+        let tunables = Tunables::new();
+        let sizes = [(10.0, 10.0), (50.0, 50.0), (100.0, 100.0), (60.0, 600.0), (800.0, 600.0)];
+
+        for &(width, height) in &sizes {
+            for seed in 0..20 {
+                let mut level = generate(width, height, seed, tunables, 3);
+                let player = level.player().physics;
+
+                for (_, entity) in level.iter() {
+                    if entity.shield.is_some() {
+                        assert!(entity.physics.position.distance(player.position) >= tunables.player_radius + tunables.enemy_radius);
+                    } else if entity.player.is_none() {
+                        assert!(!entity.physics.bounds().overlaps(&player.bounds()), "player in a wall in {}x{} seed {}", width, height, seed);
+                    }
+                }
+
+                for _ in 0..50 {
+                    level.step(&[], 10);
+                }
+            }
+        }
+    }
+}
@@ -1,74 +1,117 @@
 use std::f32;
+use std::collections::{HashMap, BTreeSet};
 
 use sdl2::pixels::Color;
 
 use vec2;
 use vec2::Vec2;
-use shape::{Shape, CollisionShape, AABB, Circle};
+use shape::{Shape, CollisionShape, AABB, Circle, Polygon, Capsule};
 use ray::Ray;
 use line::LineSegment;
-use animation::Animation;
+use components::{Collider, Health, ContactDamage, Lifetime, Renderable, RenderStyle};
 use player::Player;
-use enemy::Enemy;
+use shield::{Shield, ShieldHit};
+use gun::Gun;
+use ai::Ai;
 use bullet::{Bullet, BulletType};
-
-const PLAYER_WIDTH: f32 = 20.0;
+use command::Command;
+use rng::Rng;
+use collision::{SpatialHash, DynamicTree, collision_manifold};
+use solver::{ContactSolver, Contact};
+use store::{Store, EntityId};
+use event::GameEvent;
+use tunables::Tunables;
+use material::Material;
+use mover::Mover;
+use joint::{Joint, JointId};
+use force_field::ForceField;
 
 const WALL_THICKNESS: f32 = 20.0;
 
-#[derive(Debug, Copy, Clone)]
-pub enum EntityType {
-    Player(Player),
-    Wall,
-    Bullet(Bullet),
-    Animation(Animation),
-    Enemy(Enemy)
+// Around the size of an enemy, so most bodies only land in a cell or four
+const BROADPHASE_CELL_SIZE: f32 = 64.0;
+
+// How far a fast body is left inside whatever it ran into, so the collision
+// pass sees the contact and bounces it
+const CCD_SLOP: f32 = 0.01;
+
+// Collision categories. Two bodies only touch when each one's mask has the
+// other's category in it.
+pub const CATEGORY_WALL: u32 = 1 << 0;
+pub const CATEGORY_CRATE: u32 = 1 << 1;
+pub const CATEGORY_PLAYER: u32 = 1 << 2;
+pub const CATEGORY_ENEMY: u32 = 1 << 3;
+pub const CATEGORY_PLAYER_BULLET: u32 = 1 << 4;
+pub const CATEGORY_ENEMY_BULLET: u32 = 1 << 5;
+pub const CATEGORY_PICKUP: u32 = 1 << 6;
+
+pub const MASK_ALL: u32 = !0;
+
+// What blocks seeing and aiming
+pub const LINE_OF_SIGHT_MASK: u32 = MASK_ALL & !CATEGORY_PICKUP;
+
+// Render layers
+const BODY_LAYER: u8 = 0;
+const BULLET_LAYER: u8 = 1;
+const EFFECT_LAYER: u8 = 2;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LevelStatus {
+    Running,
+    Cleared,
+    PlayerDied
 }
 
+// Everything in a level is physics plus whichever components it needs.
+// What an entity is comes from the combination, a turret is just a wall
+// with health, a gun and an AI.
 #[derive(Debug, Copy, Clone)]
 pub struct Entity {
-    pub entity_type: EntityType,
-    pub physics: Physics
+    pub physics: Physics,
+
+    pub collider: Option<Collider>,
+    pub renderable: Option<Renderable>,
+    pub health: Option<Health>,
+    pub shield: Option<Shield>,
+    pub gun: Option<Gun>,
+    pub ai: Option<Ai>,
+    pub lifetime: Option<Lifetime>,
+    pub contact_damage: Option<ContactDamage>,
+    pub bullet: Option<Bullet>,
+    pub player: Option<Player>,
+    pub mover: Option<Mover>,
+    pub force_field: Option<ForceField>
 }
 
 impl Entity {
-    pub fn new(entity_type: EntityType, physics: Physics) -> Entity {
-        Entity { entity_type: entity_type, physics: physics }
-    }
+    pub fn new(physics: Physics) -> Entity {
+        Entity {
+            physics,
 
-    pub fn player(&self) -> &Player {
-        match self.entity_type {
-            EntityType::Player(ref player) => player,
-            _ => panic!("Tried to get a player from {:?}", self)
+            collider: None,
+            renderable: None,
+            health: None,
+            shield: None,
+            gun: None,
+            ai: None,
+            lifetime: None,
+            contact_damage: None,
+            bullet: None,
+            player: None,
+            mover: None,
+            force_field: None
         }
     }
 
-    pub fn player_mut(&mut self) -> &mut Player {
-        match self.entity_type {
-            EntityType::Player(ref mut player) => player,
-            _ => panic!("Tried to get a player from {:?}", self)
-        }
+    pub fn interpolated(&self, alpha: f32) -> Entity {
+        let mut entity = *self;
+        entity.physics.position = self.physics.interpolated_position(alpha);
+        entity.physics.angle = self.physics.interpolated_angle(alpha);
+        entity
     }
 
-    pub fn bullet(&self) -> &Bullet {
-        match self.entity_type {
-            EntityType::Bullet(ref bullet) => bullet,
-            _ => panic!("Tried to get a bullet from {:?}", self)
-        }
-    }
-
-    pub fn animation(&self) -> &Animation {
-        match self.entity_type {
-            EntityType::Animation(ref animation) => animation,
-            _ => panic!("Tried to get an animation from {:?}", self)
-        }
-    }
-
-    pub fn animation_mut(&mut self) -> &mut Animation {
-        match self.entity_type {
-            EntityType::Animation(ref mut animation) => animation,
-            _ => panic!("Tried to get an animation from {:?}", self)
-        }
+    pub fn is_dead(&self) -> bool {
+        self.health.is_some_and(|health| health.is_dead())
     }
 
 }
@@ -78,193 +121,1085 @@ impl Entity {
 pub struct Physics {
     pub shape: Shape,
     pub position: Vec2,
+    pub previous_position: Vec2,
     pub velocity: Vec2,
     pub acceleration: Vec2,
 
-    pub restitution: f32,
-    pub inv_mass: f32
+    // Radians, turning from the x axis towards the y axis
+    pub angle: f32,
+    pub previous_angle: f32,
+    // Radians per second
+    pub angular_velocity: f32,
+
+    pub material: Material,
+    pub inv_mass: f32,
+    // Bodies that don't turn when pushed keep this at 0
+    pub inv_inertia: f32,
+
+    // Moved by setting its velocity, usually from a Mover. Like walls it's
+    // never pushed, but it pushes whatever is in its way.
+    pub is_kinematic: bool,
+
+    // Fast circles are swept from where they were to where they're going, so
+    // they stop at whatever they'd hit instead of passing through it
+    pub is_fast: bool,
+
+    // Turned to point where it's heading instead of being spun by impulses
+    pub faces_movement: bool,
+
+    // Which CATEGORY_ it is and which ones it touches
+    pub category: u32,
+    pub mask: u32,
+
+    // Sensors notice what overlaps them but never push back, and rays,
+    // bullets and fast bodies go straight through
+    pub is_sensor: bool,
+
+    // Sleeping bodies stay where they are and hold still like walls until
+    // they're pushed. resting_time is how long it's been barely moving, in ms.
+    pub is_sleeping: bool,
+    pub resting_time: u32
 }
 
 impl Physics {
 
+    pub fn new(shape: Shape, position: Vec2, material: Material, inv_mass: f32) -> Physics {
+        Physics {
+            shape,
+            position,
+            previous_position: position,
+            velocity: vec2::ORIGIN,
+            acceleration: vec2::ORIGIN,
+
+            angle: 0.0,
+            previous_angle: 0.0,
+            angular_velocity: 0.0,
+
+            material,
+            inv_mass,
+            inv_inertia: 0.0,
+
+            is_kinematic: false,
+
+            is_fast: false,
+
+            faces_movement: false,
+
+            category: CATEGORY_WALL,
+            mask: MASK_ALL,
+
+            is_sensor: false,
+
+            is_sleeping: false,
+            resting_time: 0
+        }
+    }
+
+    pub fn collides_with(&self, other: &Physics) -> bool {
+        (self.mask & other.category) != 0 && (other.mask & self.category) != 0
+    }
+
+    pub fn is_in(&self, mask: u32) -> bool {
+        (self.category & mask) != 0
+    }
+
+    // Lets impulses spin the body, as if its mass were spread evenly over
+    // its shape
+    pub fn set_mass(&mut self, mass: f32) {
+        self.inv_mass = 1.0 / mass;
+        self.inv_inertia = 1.0 / self.shape.inertia(mass);
+    }
+
+    // Position between the previous and the current tick, for drawing
+    // frames that fall in between fixed physics steps.
+    pub fn interpolated_position(&self, alpha: f32) -> Vec2 {
+        self.previous_position + (self.position - self.previous_position) * alpha
+    }
+
+    pub fn interpolated_angle(&self, alpha: f32) -> f32 {
+        self.previous_angle + (self.angle - self.previous_angle) * alpha
+    }
+
+    // Velocity of the point on the body at the given offset from its position
+    pub fn velocity_at(&self, offset: Vec2) -> Vec2 {
+        self.velocity + offset.perpendicular() * self.angular_velocity
+    }
+
     pub fn collision_shape(&self) -> CollisionShape {
         match self.shape {
             Shape::Rect { extent } => {
                 let half_extent = extent / 2.0;
-                CollisionShape::AABB(AABB::new(self.position - half_extent, self.position + half_extent))
+
+                if self.angle == 0.0 {
+                    CollisionShape::AABB(AABB::new(self.position - half_extent, self.position + half_extent))
+                } else if extent.x == 0.0 || extent.y == 0.0 {
+                    // A flat box has no polygon, turned it's just a line
+                    let offset = half_extent.rotate(self.angle);
+                    CollisionShape::Capsule(Capsule::new(self.position - offset, self.position + offset, 0.0))
+                } else {
+                    let corners = Polygon::from_aabb(&AABB::new(half_extent * -1.0, half_extent));
+                    CollisionShape::Polygon(corners.rotated(self.angle).translated(self.position))
+                }
             }
             Shape::Circle { radius } => {
                 CollisionShape::Circle(Circle::new(self.position, radius))
             }
+            Shape::Polygon(ref polygon) => {
+                CollisionShape::Polygon(polygon.rotated(self.angle).translated(self.position))
+            }
+            Shape::Capsule { half_length, radius } => {
+                let offset = Vec2::new(half_length, 0.0).rotate(self.angle);
+                CollisionShape::Capsule(Capsule::new(self.position - offset, self.position + offset, radius))
+            }
         }
     }
 
+    // Smallest box around the shape
+    pub fn bounds(&self) -> AABB {
+        self.collision_shape().bounds()
+    }
+
+    pub fn is_static(&self) -> bool {
+        self.inv_mass == 0.0
+    }
+
+    // Barely moving and not trying to
+    pub fn is_resting(&self, tunables: &Tunables) -> bool {
+        self.acceleration.magnitude_squared() == 0.0 &&
+            self.velocity.magnitude() < tunables.sleep_speed &&
+            self.angular_velocity.abs() < tunables.sleep_angular_speed
+    }
+
+    pub fn wake(&mut self) {
+        self.is_sleeping = false;
+        self.resting_time = 0;
+    }
+
 }
 
 pub struct Level {
     pub width: f32,
     pub height: f32,
 
-    pub collision_entities: Vec<Entity>,
+    pub seed: u64,
+    pub rng: Rng,
+
+    pub tunables: Tunables,
 
-    pub bullets: Vec<Entity>,
-    pub animations: Vec<Entity>
+    pub entities: Store<Entity>,
+    pub player_id: Option<EntityId>,
+
+    // Every collider's bounds, for ray and overlap queries
+    tree: DynamicTree<EntityId>,
+    proxies: HashMap<EntityId, usize>,
+
+    // (sensor, other) pairs that overlapped in the last step
+    sensor_contacts: BTreeSet<(EntityId, EntityId)>,
+
+    // Ties between bodies, solved along with the contacts
+    pub joints: Store<Joint, JointId>,
+
+    // Keeps last step's contact impulses
+    solver: ContactSolver,
+
+    // What happened during the last step
+    pub events: Vec<GameEvent>,
+
+    // How many steps the level has taken
+    pub ticks: u64
 }
 
 
 impl Level {
-    pub fn new(width: f32, height: f32) -> Level {
+    pub fn new(width: f32, height: f32, seed: u64, tunables: Tunables) -> Level {
+        let mut level = Level::empty(width, height, seed, tunables);
+        let tunables = &tunables;
+
+        level.insert(make_player(tunables, Vec2::new(width / 2.0, height / 2.0)));
+
+        level.insert(make_wall(width, WALL_THICKNESS, Vec2::new(width / 2.0, WALL_THICKNESS / 2.0)));
+        level.insert(make_wall(WALL_THICKNESS, height - (2.0 * WALL_THICKNESS), Vec2::new(width - (WALL_THICKNESS / 2.0), height / 2.0)));
+        level.insert(make_wall(width, WALL_THICKNESS, Vec2::new(width / 2.0, height - (WALL_THICKNESS / 2.0))));
+        level.insert(make_wall(WALL_THICKNESS, height - (2.0 * WALL_THICKNESS), Vec2::new(WALL_THICKNESS / 2.0, height / 2.0)));
+
+        level
+    }
+
+    // A level with nothing in it, not even the player
+    pub fn empty(width: f32, height: f32, seed: u64, tunables: Tunables) -> Level {
         Level {
-            width: width,
-            height: height,
+            width,
+            height,
+
+            seed,
+            rng: Rng::new(seed),
+
+            tunables,
+
+            entities: Store::new(),
+            player_id: None,
+
+            tree: DynamicTree::new(),
+            proxies: HashMap::new(),
+
+            sensor_contacts: BTreeSet::new(),
+
+            joints: Store::new(),
+
+            solver: ContactSolver::new(),
+
+            events: vec![],
+
+            ticks: 0
+        }
+    }
 
-            collision_entities: vec![
-                make_player(width, height),
+    // Inserting a player entity makes it the level's player
+    pub fn insert(&mut self, entity: Entity) -> EntityId {
+        let is_player = entity.player.is_some();
 
-                make_wall(width, WALL_THICKNESS, Vec2::new(width / 2.0, WALL_THICKNESS / 2.0)),
-                make_wall(WALL_THICKNESS, height - (2.0 * WALL_THICKNESS), Vec2::new(width - (WALL_THICKNESS / 2.0), height / 2.0)),
-                make_wall(width, WALL_THICKNESS, Vec2::new(width / 2.0, height - (WALL_THICKNESS / 2.0))),
-                make_wall(WALL_THICKNESS, height - (2.0 * WALL_THICKNESS), Vec2::new(WALL_THICKNESS / 2.0, height / 2.0))
-           ],
+        let id = self.entities.insert(entity);
+        if is_player {
+            self.player_id = Some(id);
+        }
 
-           bullets: vec![],
-           animations: vec![]
+        if entity.collider.is_some() {
+            let proxy = self.tree.insert(entity.physics.bounds(), id);
+            self.proxies.insert(id, proxy);
         }
+
+        id
+    }
+
+    pub fn remove(&mut self, id: EntityId) -> Option<Entity> {
+        if self.player_id == Some(id) {
+            self.player_id = None;
+        }
+
+        if let Some(proxy) = self.proxies.remove(&id) {
+            self.tree.remove(proxy);
+        }
+
+        // Joints go with either of their bodies
+        let joints = self.joints.iter()
+            .filter(|&(_, joint)| joint.a == id || joint.b == id)
+            .map(|(joint_id, _)| joint_id)
+            .collect::<Vec<JointId>>();
+
+        for joint_id in joints {
+            self.joints.remove(joint_id);
+        }
+
+        self.entities.remove(id)
+    }
+
+    pub fn add_joint(&mut self, joint: Joint) -> JointId {
+        self.joints.insert(joint)
+    }
+
+    pub fn remove_joint(&mut self, id: JointId) -> Option<Joint> {
+        self.joints.remove(id)
+    }
+
+    pub fn get(&self, id: EntityId) -> Option<&Entity> {
+        self.entities.get(id)
+    }
+
+    pub fn get_mut(&mut self, id: EntityId) -> Option<&mut Entity> {
+        self.entities.get_mut(id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=(EntityId, &Entity)> {
+        self.entities.iter()
     }
 
     pub fn player(&self) -> &Entity {
-        self.collision_entities.first().unwrap()
+        self.player_id.and_then(|id| self.entities.get(id)).expect("Level has no player")
     }
 
     pub fn player_mut(&mut self) -> &mut Entity {
-        self.collision_entities.first_mut().unwrap()
+        let id = self.player_id.expect("Level has no player");
+        self.entities.get_mut(id).expect("Level has no player")
+    }
+
+    pub fn collision_entities(&self) -> impl Iterator<Item=(EntityId, &Entity)> {
+        self.entities.iter().filter(|&(_, entity)| entity.collider.is_some())
     }
 
-    pub fn non_player_collision_entities(&self) -> &[Entity] {
-        self.collision_entities.split_first().unwrap().1
+    pub fn non_player_collision_entities(&self) -> impl Iterator<Item=(EntityId, &Entity)> {
+        let player_id = self.player_id;
+        self.collision_entities().filter(move |&(id, _)| Some(id) != player_id)
     }
 
-    pub fn non_player_collision_entities_mut(&mut self) -> &[Entity] {
-        self.collision_entities.split_first_mut().unwrap().1
+    // Bounds of every collider, for the queries in query.rs
+    pub fn tree(&self) -> &DynamicTree<EntityId> {
+        &self.tree
+    }
+
+    // Call after moving colliders around, only ones that left their
+    // fattened bounds change the tree
+    fn update_tree(&mut self) {
+        for (id, entity) in self.entities.iter() {
+            if entity.physics.is_static() && !entity.physics.is_kinematic {
+                continue;
+            }
+
+            if let Some(&proxy) = self.proxies.get(&id) {
+                self.tree.update(proxy, entity.physics.bounds());
+            }
+        }
+    }
+
+    fn update_proxy(&mut self, id: EntityId) {
+        if let (Some(&proxy), Some(entity)) = (self.proxies.get(&id), self.entities.get(id)) {
+            self.tree.update(proxy, entity.physics.bounds());
+        }
+    }
+
+    // Values used every tick take effect straight away, bodies keep the
+    // size and mass they were made with
+    pub fn set_tunables(&mut self, tunables: Tunables) {
+        self.tunables = tunables;
+
+        for (_, entity) in self.entities.iter_mut() {
+            if let Some(ref mut gun) = entity.gun {
+                gun.boom_charge_time = tunables.boom_charge_time;
+            }
+        }
     }
 
     pub fn update(&mut self, time_delta: u32) {
-        let (player, entities) = self.collision_entities.split_first_mut().unwrap();
+        let shield_regen = self.tunables.shield_regen;
 
-        player.player_mut().update(time_delta);
+        for (_, entity) in self.entities.iter_mut() {
+            if let Some(ref mut gun) = entity.gun {
+                gun.update(time_delta);
+            }
+
+            if let Some(ref mut shield) = entity.shield {
+                shield.update(time_delta, shield_regen);
+            }
 
-        for entity in entities {
-            match entity.entity_type {
-                EntityType::Player(ref mut player) => player.update(time_delta),
-                EntityType::Enemy(ref mut enemy) => enemy.update(time_delta),
-                _ => {}
+            if let Some(ref mut lifetime) = entity.lifetime {
+                lifetime.update(time_delta);
             }
         }
+    }
+
+    // Runs a full tick of the simulation: timers, the player's commands,
+    // AI, movement, collisions, drag, removing whatever died or expired and
+    // line of sight. Velocities and accelerations are per second,
+    // time_delta is in ms.
+    pub fn step(&mut self, commands: &[Command], time_delta: u32) {
+        let dt = time_delta as f32 / 1000.0;
+
+        self.events.clear();
+        self.update_tree();
+        self.update(time_delta);
 
-        for animation in &mut self.animations {
-            animation.animation_mut().update(time_delta)
+        for command in commands {
+            self.apply_command(*command);
         }
+
+        self.run_ai();
+        self.drive_kinematic(dt);
+        self.apply_force_fields(dt);
+        self.integrate(dt);
+        self.resolve_entity_collisions(dt);
+        self.update_tree();
+        self.crush();
+        self.resolve_bullet_collisions();
+        self.apply_drag(dt);
+        self.update_sleep(time_delta);
+        self.remove_dead();
+        self.update_line_of_sight();
+
+        self.ticks += 1;
     }
 
-}
+    pub fn apply_command(&mut self, command: Command) {
+        let player_id = self.player_id.expect("Level has no player");
 
-pub fn make_player(level_width: f32, level_height: f32) -> Entity {
-    Entity::new(
-        EntityType::Player(Player::new()),
-        Physics {
-            //shape: Shape::Rect { extent: Vec2::new(PLAYER_WIDTH, PLAYER_HEIGHT) },
-            shape: Shape::Circle { radius: PLAYER_WIDTH / 2.0 },
-            position: Vec2::new(level_width / 2.0, level_height / 2.0),
-            velocity: vec2::ORIGIN,
-            acceleration: vec2::ORIGIN,
+        match command {
+            Command::Move(acceleration) => {
+                self.player_mut().physics.acceleration = acceleration;
+            }
+            Command::StartGunCharging => {
+                if let Some(gun) = self.player_mut().gun.as_mut() {
+                    gun.start_charging();
+                }
+            }
+            Command::FireGun(target) => {
+                self.fire_gun(player_id, target);
+            }
+            Command::CancelGunCharging => {
+                if let Some(gun) = self.player_mut().gun.as_mut() {
+                    gun.cancel_charging();
+                }
+            }
+            Command::QuickFire(target) => {
+                self.fire_bullet(player_id, BulletType::PewPew, target);
+            }
+            Command::LookAt(target) => {
+                if let Some(player) = self.player_mut().player.as_mut() {
+                    player.aiming_at = target;
+                }
+            }
+        }
+    }
+
+    fn fire_gun(&mut self, shooter: EntityId, target: Vec2) -> Option<EntityId> {
+        let bullet_type = self.entities.get_mut(shooter)
+            .and_then(|entity| entity.gun.as_mut())
+            .and_then(|gun| gun.fire());
+
+        bullet_type.and_then(|bullet_type| self.fire_bullet(shooter, bullet_type, target))
+    }
+
+    // Shots with nowhere to go are skipped
+    fn fire_bullet(&mut self, shooter: EntityId, bullet_type: BulletType, target: Vec2) -> Option<EntityId> {
+        let bullet = make_bullet(&self.tunables, shooter, self.get(shooter).unwrap(), bullet_type, target)?;
+        Some(self.insert(bullet))
+    }
+
+    pub fn status(&self) -> LevelStatus {
+        let player_id = self.player_id;
+
+        if self.player().is_dead() {
+            LevelStatus::PlayerDied
+        } else if !self.entities.iter().any(|(id, entity)| Some(id) != player_id && (entity.health.is_some() || entity.ai.is_some())) {
+            // Everything that can be killed or fights back is gone
+            LevelStatus::Cleared
+        } else {
+            LevelStatus::Running
+        }
+    }
+
+    // Only things in the mask get in the way
+    fn can_see(&self, viewer: EntityId, target: EntityId, mask: u32) -> bool {
+        let (from, to) = match (self.get(viewer), self.get(target)) {
+            (Some(from), Some(to)) => (from.physics.position, to.physics.position),
+            _ => return false
+        };
+
+        let ray = Ray::from_segment(&LineSegment::new(from, to));
+
+        match self.ray_cast(&ray, |id, entity| id == target || (id != viewer && !entity.physics.is_sensor && entity.physics.is_in(mask))) {
+            Some((id, _)) => id == target,
+            None => false
+        }
+    }
+
+    fn drive_kinematic(&mut self, dt: f32) {
+        for (_, entity) in self.entities.iter_mut() {
+            if let Some(ref mut mover) = entity.mover {
+                entity.physics.velocity = mover.velocity(entity.physics.position, dt);
+            }
+        }
+    }
+
+    // Fields only push what their mask lets in. Walls, doors and other
+    // fields stay where they are.
+    fn apply_force_fields(&mut self, dt: f32) {
+        let fields = self.entities.iter()
+            .filter_map(|(_, entity)| {
+                entity.force_field.map(|field| (field, entity.physics.position, entity.physics.collision_shape(), entity.physics.mask))
+            })
+            .collect::<Vec<(ForceField, Vec2, CollisionShape, u32)>>();
+
+        if fields.is_empty() {
+            return;
+        }
+
+        for (_, entity) in self.entities.iter_mut() {
+            let physics = &mut entity.physics;
+
+            let is_pushed = entity.bullet.is_some() ||
+                (entity.collider.is_some() && !physics.is_static() && !physics.is_sensor);
+            if !is_pushed {
+                continue;
+            }
 
-            restitution: 1.5,
-            inv_mass: 1.0 / 20.0
+            // Anything set moving here wakes up in integrate
+            for &(field, center, ref shape, mask) in &fields {
+                if physics.is_in(mask) && shape.contains_point(physics.position) {
+                    physics.velocity += field.acceleration(center, physics.position) * dt;
+                }
+            }
+        }
+    }
+
+    // Knocks everything within radius away from center, hardest in the
+    // middle and not at all at the edge. Heavier bodies move less and walls
+    // not at all. Returns whatever was in reach.
+    pub fn explode(&mut self, center: Vec2, radius: f32, impulse: f32) -> Vec<EntityId> {
+        let hit = self.overlap_circle(center, radius, |_, entity| !entity.physics.is_static() && !entity.physics.is_sensor);
+
+        for &id in &hit {
+            let physics = &mut self.entities.get_mut(id).unwrap().physics;
+            let away = physics.position - center;
+            let distance = away.magnitude();
+
+            physics.wake();
+
+            if distance > 0.0 {
+                let falloff = (1.0 - distance / radius).max(0.0);
+                physics.velocity += away / distance * impulse * falloff * physics.inv_mass;
+            }
+        }
+
+        hit
+    }
+
+    fn run_ai(&mut self) {
+        let player_id = match self.player_id {
+            Some(id) => id,
+            None => return
+        };
+        let player_position = self.player().physics.position;
+
+        let turrets = self.entities.iter()
+            .filter_map(|(id, entity)| {
+                match (entity.ai, entity.gun) {
+                    (Some(Ai::Turret { charge_time }), Some(_)) => Some((id, charge_time)),
+                    _ => None
+                }
+            })
+            .collect::<Vec<(EntityId, u32)>>();
+
+        for (id, charge_time) in turrets {
+            // Turret shots go through other enemies, so they don't block the view
+            let can_see_player = self.can_see(id, player_id, LINE_OF_SIGHT_MASK & !CATEGORY_ENEMY);
+            let gun = self.entities.get_mut(id).unwrap().gun.as_mut().unwrap();
+
+            gun.target = player_position;
+
+            if !can_see_player {
+                gun.is_charging = false;
+            } else if !gun.is_charging {
+                gun.start_charging();
+            } else if gun.charge_time >= charge_time {
+                self.fire_gun(id, player_position);
+            }
+        }
+    }
+
+    fn integrate(&mut self, dt: f32) {
+        let mut fast = vec![];
+
+        for (id, entity) in self.entities.iter_mut() {
+            entity.physics.previous_position = entity.physics.position;
+            entity.physics.previous_angle = entity.physics.angle;
+
+            // Woken by anything that set it moving since last step
+            if entity.physics.is_sleeping {
+                if entity.physics.acceleration.magnitude_squared() > 0.0 || entity.physics.velocity.magnitude_squared() > 0.0 {
+                    entity.physics.wake();
+                } else {
+                    continue;
+                }
+            }
+
+            entity.physics.angle += entity.physics.angular_velocity * dt;
+
+            if entity.physics.faces_movement && entity.physics.velocity.magnitude_squared() > 0.0 {
+                entity.physics.angle = entity.physics.velocity.y.atan2(entity.physics.velocity.x);
+            }
+
+            if entity.physics.is_fast && entity.collider.is_some() {
+                fast.push(id);
+            } else {
+                entity.physics.position += entity.physics.velocity * dt;
+            }
+        }
+
+        if fast.is_empty() {
+            return;
+        }
+
+        self.update_tree();
+
+        // Fast bodies sweep against everything else where it is now. Anything
+        // they already touch is left to the collision pass.
+        for id in fast {
+            let physics = self.entities.get(id).unwrap().physics;
+            let motion = physics.velocity * dt;
+
+            let impact = match physics.shape {
+                Shape::Circle { radius } =>
+                    self.sweep_circle(physics.position, radius, motion, |other_id, other, impact| {
+                        other_id != id && impact.time > 0.0 && !other.physics.is_sensor && physics.collides_with(&other.physics)
+                    }),
+                _ => None
+            };
+
+            let position = match impact {
+                Some((_, impact)) => physics.position + motion * impact.time - impact.normal * CCD_SLOP,
+                None => physics.position + motion
+            };
+
+            self.entities.get_mut(id).unwrap().physics.position = position;
+            self.update_proxy(id);
+        }
+    }
+
+    fn resolve_entity_collisions(&mut self, dt: f32) {
+        let mut broadphase = SpatialHash::new(BROADPHASE_CELL_SIZE);
+        let mut ids = vec![];
+
+        for (id, entity) in self.collision_entities() {
+            // Doors still have to set off sensors
+            let physics = &entity.physics;
+            broadphase.insert(physics.bounds(), physics.is_static() && !physics.is_kinematic && !physics.is_sensor);
+            ids.push(id);
+        }
+
+        let mut contacts = vec![];
+        let mut sensor_contacts = BTreeSet::new();
+
+        for (a, b) in broadphase.pairs() {
+            let (id_a, id_b) = (ids[a], ids[b]);
+            let (entity_a, entity_b) = self.entities.get_pair_mut(id_a, id_b).unwrap();
+
+            match collision_manifold(entity_a, entity_b) {
+                Some(_) if entity_a.physics.is_sensor => {
+                    sensor_contacts.insert((id_a, id_b));
+                }
+                Some(_) if entity_b.physics.is_sensor => {
+                    sensor_contacts.insert((id_b, id_a));
+                }
+                Some(manifold) => {
+                    contacts.push(Contact::new(id_a, id_b, manifold));
+                    self.events.push(GameEvent::Collision { a: id_a, b: id_b, manifold });
+
+                    if apply_contact_damage(entity_a, entity_b, dt) {
+                        self.events.push(GameEvent::EntityDied { entity: id_a, killer: None });
+                    }
+                    if apply_contact_damage(entity_b, entity_a, dt) {
+                        self.events.push(GameEvent::EntityDied { entity: id_b, killer: None });
+                    }
+                }
+                None => {}
+            }
+        }
+
+        let tunables = self.tunables;
+        self.solver.solve(&mut self.entities, contacts, &mut self.joints, dt, &tunables);
+
+        self.update_sensor_contacts(sensor_contacts);
+    }
+
+    // Compares against the last step. Pairs that stopped overlapping
+    // because one of them was removed still get an exit.
+    fn update_sensor_contacts(&mut self, sensor_contacts: BTreeSet<(EntityId, EntityId)>) {
+        for &(sensor, other) in &sensor_contacts {
+            if self.sensor_contacts.contains(&(sensor, other)) {
+                self.events.push(GameEvent::SensorStay { sensor, other });
+            } else {
+                self.events.push(GameEvent::SensorEnter { sensor, other });
+            }
+        }
+
+        for &(sensor, other) in self.sensor_contacts.difference(&sensor_contacts) {
+            self.events.push(GameEvent::SensorExit { sensor, other });
+        }
+
+        self.sensor_contacts = sensor_contacts;
+    }
+
+    // Anything a moving wall still overlaps deeply after solving couldn't
+    // get out of its way, so it's squashed. Only things with health die.
+    fn crush(&mut self) {
+        let crush_depth = self.tunables.crush_depth;
+
+        let mut crushed = BTreeSet::new();
+
+        for (_, wall) in self.entities.iter() {
+            if !wall.physics.is_kinematic || wall.collider.is_none() {
+                continue;
+            }
+
+            for id in self.overlapping(&wall.physics.bounds()) {
+                let entity = self.entities.get(id).unwrap();
+                let penetration = collision_manifold(entity, wall).map_or(0.0, |manifold| manifold.penetration);
+
+                if !entity.physics.is_static() && entity.health.is_some() && !entity.is_dead() && penetration > crush_depth {
+                    crushed.insert(id);
+                }
+            }
+        }
+
+        for id in crushed {
+            let health = self.entities.get_mut(id).unwrap().health.as_mut().unwrap();
+            let amount = health.health;
+            health.take_damage(amount);
+            self.events.push(GameEvent::EntityDied { entity: id, killer: None });
+        }
+    }
+
+    fn resolve_bullet_collisions(&mut self) {
+        let tunables = self.tunables;
+
+        // Bullets are swept with their radius from where they were last tick.
+        // They start out touching whatever fired them, so that never counts.
+        let hits = self.entities.iter()
+            .filter_map(|(bullet_id, entity)| {
+                let (owner, radius) = match (entity.bullet, entity.physics.shape) {
+                    (Some(bullet), Shape::Circle { radius }) => (bullet.owner, radius),
+                    _ => return None
+                };
+
+                let from = entity.physics.previous_position;
+                let motion = entity.physics.position - from;
+
+                self.sweep_circle(from, radius, motion, |id, other, _| {
+                    id != owner && !other.physics.is_sensor && entity.physics.collides_with(&other.physics)
+                }).map(|(hit_id, impact)| {
+                    let center = from + motion * impact.time;
+                    (bullet_id, hit_id, center, center - impact.normal * radius)
+                })
+            })
+            .collect::<Vec<(EntityId, EntityId, Vec2, Vec2)>>();
+
+        for (bullet_id, hit_id, center, point) in hits {
+            let mut bullet_entity = self.remove(bullet_id).unwrap();
+            bullet_entity.physics.position = center;
+            let bullet = bullet_entity.bullet.unwrap();
+            self.insert(make_animation(bullet.color(), point));
+
+            let hit_entity = match self.entities.get_mut(hit_id) {
+                Some(entity) => entity,
+                None => continue
+            };
+
+            let damageable = hit_entity.health.is_some() || hit_entity.shield.is_some();
+            self.events.push(GameEvent::BulletHit { bullet: bullet_id, shooter: bullet.owner, target: hit_id, point, damageable });
+
+            let shield_hit = match hit_entity.shield {
+                Some(ref mut shield) => shield.take_hit(hit_entity.physics.position, bullet_entity.physics.position, &bullet, tunables.boom_shield_damage),
+                None => ShieldHit::Through
+            };
+
+            match shield_hit {
+                ShieldHit::Blocked => {}
+                ShieldHit::Broken { slice } => {
+                    self.events.push(GameEvent::ShieldSliceBroken { entity: hit_id, shooter: bullet.owner, slice });
+                }
+                ShieldHit::Through => {
+                    if let Some(ref mut health) = hit_entity.health {
+                        let was_dead = health.is_dead();
+                        health.take_damage(bullet.damage(&tunables));
+
+                        if !was_dead && health.is_dead() {
+                            self.events.push(GameEvent::EntityDied { entity: hit_id, killer: Some(bullet.owner) });
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn apply_drag(&mut self, dt: f32) {
+        let drag = self.tunables.drag;
+
+        for (_, entity) in self.entities.iter_mut() {
+            if entity.collider.is_some() && !entity.physics.is_sleeping && !entity.physics.is_kinematic {
+                entity.physics.velocity += (entity.physics.acceleration - entity.physics.velocity * drag) * dt;
+                entity.physics.angular_velocity -= entity.physics.angular_velocity * drag * dt;
+            }
+        }
+    }
+
+    // Bodies that have been resting long enough fall asleep
+    fn update_sleep(&mut self, time_delta: u32) {
+        let tunables = self.tunables;
+
+        for (_, entity) in self.entities.iter_mut() {
+            let physics = &mut entity.physics;
+
+            if entity.collider.is_none() || physics.is_static() || physics.is_sensor || physics.is_sleeping {
+                continue;
+            }
+
+            if !physics.is_resting(&tunables) {
+                physics.resting_time = 0;
+                continue;
+            }
+
+            physics.resting_time += time_delta;
+
+            if physics.resting_time >= tunables.sleep_time {
+                physics.is_sleeping = true;
+                physics.velocity = vec2::ORIGIN;
+                physics.angular_velocity = 0.0;
+            }
+        }
+    }
+
+    // The player stays around when dead, the level status reports it
+    fn remove_dead(&mut self) {
+        let player_id = self.player_id;
+
+        let dead = self.entities.iter()
+            .filter(|&(id, entity)| {
+                let expired = entity.lifetime.is_some_and(|lifetime| lifetime.is_expired());
+                Some(id) != player_id && (expired || entity.is_dead())
+            })
+            .map(|(id, _)| id)
+            .collect::<Vec<EntityId>>();
+
+        for id in dead {
+            self.remove(id);
+        }
+    }
+
+    fn update_line_of_sight(&mut self) {
+        let aiming_at = match self.player().player {
+            Some(ref player) => player.aiming_at,
+            None => return
+        };
+
+        let los_ray = Ray::from_segment(&LineSegment::new(self.player().physics.position, aiming_at));
+
+        let player_id = self.player_id;
+        let los_end = match self.ray_cast(&los_ray, |id, entity| {
+            Some(id) != player_id && !entity.physics.is_sensor && entity.physics.is_in(LINE_OF_SIGHT_MASK)
+        }) {
+            Some((_, p)) => p,
+            None => los_ray.origin + (self.width * los_ray.direction).normalize()
+        };
+
+        let player = self.player_mut();
+        if let Some(player) = player.player.as_mut() {
+            player.looking_at = los_end;
+        }
+        if let Some(gun) = player.gun.as_mut() {
+            gun.target = los_end;
         }
-    )
+    }
+
 }
-pub fn make_enemy(position: Vec2) -> Entity {
-    Entity::new(
-        EntityType::Enemy(Enemy::new(10.0)),
-        Physics {
-            //shape: Shape::Rect { extent: Vec2::new(30.0, 30.0) },
-            shape: Shape::Circle { radius: 20.0 },
-            position: position,
-            velocity: vec2::ORIGIN,
-            acceleration: vec2::ORIGIN,
 
-            restitution: 1.5,
-            inv_mass: 1.0 / 50.0
+// Returns whether the damage killed the target
+fn apply_contact_damage(target: &mut Entity, source: &Entity, dt: f32) -> bool {
+    match (target.player, target.health.as_mut(), source.contact_damage) {
+        (Some(_), Some(health), Some(contact_damage)) => {
+            let was_dead = health.is_dead();
+            health.take_damage(contact_damage.damage * dt);
+            !was_dead && health.is_dead()
         }
-    )
+        _ => false
+    }
+}
+
+pub fn make_player(tunables: &Tunables, position: Vec2) -> Entity {
+    //let shape = Shape::Rect { extent: Vec2::new(PLAYER_WIDTH, PLAYER_HEIGHT) };
+    let shape = Shape::Circle { radius: tunables.player_radius };
+
+    let mut entity = Entity::new(Physics::new(shape, position, Material::Flesh, 1.0 / tunables.player_mass));
+    entity.physics.is_fast = true;
+    entity.physics.category = CATEGORY_PLAYER;
+    entity.physics.mask = MASK_ALL & !CATEGORY_PLAYER_BULLET;
+    entity.collider = Some(Collider);
+    entity.renderable = Some(Renderable::new(Color::RGB(0, 255, 0), RenderStyle::Filled, BODY_LAYER));
+    entity.health = Some(Health::new(tunables.player_health));
+    entity.gun = Some(Gun::new(tunables.boom_charge_time));
+    entity.player = Some(Player::new());
+    entity
+}
+
+pub fn make_enemy(tunables: &Tunables, position: Vec2) -> Entity {
+    //let shape = Shape::Rect { extent: Vec2::new(30.0, 30.0) };
+    let shape = Shape::Circle { radius: tunables.enemy_radius };
+
+    let mut entity = Entity::new(Physics::new(shape, position, Material::Flesh, 1.0 / tunables.enemy_mass));
+    entity.physics.is_fast = true;
+    entity.physics.faces_movement = true;
+    entity.physics.category = CATEGORY_ENEMY;
+    entity.physics.mask = MASK_ALL & !CATEGORY_ENEMY_BULLET;
+    entity.collider = Some(Collider);
+    entity.renderable = Some(Renderable::new(Color::RGB(255, 0, 0), RenderStyle::Core { radius: tunables.enemy_radius / 2.0 }, BODY_LAYER));
+    entity.health = Some(Health::new(tunables.enemy_health));
+    entity.shield = Some(Shield::new(tunables.shield_slices));
+    entity.contact_damage = Some(ContactDamage::new(tunables.enemy_contact_damage));
+    entity
 }
 
 pub fn make_wall(width: f32, height: f32, position: Vec2) -> Entity {
-    Entity::new(
-        EntityType::Wall,
-        Physics {
-            shape: Shape::Rect { extent: Vec2::new(width, height) },
-            position: position,
-            velocity: vec2::ORIGIN,
-            acceleration: vec2::ORIGIN,
+    let shape = Shape::Rect { extent: Vec2::new(width, height) };
 
-            restitution: 1.5,
-            inv_mass: 0.0
-        }
-    )
+    let mut entity = Entity::new(Physics::new(shape, position, Material::Stone, 0.0));
+    entity.collider = Some(Collider);
+    entity.renderable = Some(Renderable::new(Color::RGB(0, 0, 0), RenderStyle::Filled, BODY_LAYER));
+    entity
 }
 
 pub fn make_circle_wall(radius: f32, position: Vec2) -> Entity {
-    Entity::new(
-        EntityType::Wall,
-        Physics {
-            shape: Shape::Circle { radius: radius },
-            position: position,
-            velocity: vec2::ORIGIN,
-            acceleration: vec2::ORIGIN,
+    let shape = Shape::Circle { radius };
 
-            restitution: 50.0,
-            inv_mass: 0.0
-        }
-    )
+    let mut entity = Entity::new(Physics::new(shape, position, Material::Rubber, 0.0));
+    entity.collider = Some(Collider);
+    entity.renderable = Some(Renderable::new(Color::RGB(0, 0, 0), RenderStyle::Filled, BODY_LAYER));
+    entity
+}
+
+// Vertices are relative to the position
+pub fn make_polygon_wall(polygon: Polygon, position: Vec2) -> Entity {
+    let shape = Shape::Polygon(polygon);
+
+    let mut entity = Entity::new(Physics::new(shape, position, Material::Stone, 0.0));
+    entity.collider = Some(Collider);
+    entity.renderable = Some(Renderable::new(Color::RGB(0, 0, 0), RenderStyle::Filled, BODY_LAYER));
+    entity
+}
+
+// Slides from closed to open and back when its Mover is told to
+pub fn make_door(width: f32, height: f32, closed: Vec2, open: Vec2, speed: f32) -> Entity {
+    let mut entity = make_wall(width, height, closed);
+    entity.renderable = Some(Renderable::new(Color::RGB(88, 110, 117), RenderStyle::Filled, BODY_LAYER));
+    entity.physics.material = Material::Metal;
+    entity.physics.is_kinematic = true;
+    entity.mover = Some(Mover::door(closed, open, speed));
+    entity
+}
+
+// Goes back and forth along the waypoints, squashing anything it pins
+// against another wall
+pub fn make_moving_wall(width: f32, height: f32, mover: Mover) -> Entity {
+    let mut entity = make_wall(width, height, mover.waypoints()[0]);
+    entity.renderable = Some(Renderable::new(Color::RGB(88, 110, 117), RenderStyle::Filled, BODY_LAYER));
+    entity.physics.is_kinematic = true;
+    entity.mover = Some(mover);
+    entity
+}
+
+// A wall that shoots at the player whenever it can see them
+pub fn make_turret(tunables: &Tunables, size: f32, position: Vec2) -> Entity {
+    let mut entity = make_wall(size, size, position);
+    entity.renderable = Some(Renderable::new(Color::RGB(101, 123, 131), RenderStyle::Filled, BODY_LAYER));
+    entity.physics.material = Material::Metal;
+    entity.physics.category = CATEGORY_ENEMY;
+    entity.physics.mask = MASK_ALL & !CATEGORY_ENEMY_BULLET;
+    entity.gun = Some(Gun::new(tunables.boom_charge_time));
+    entity.ai = Some(Ai::Turret { charge_time: tunables.turret_charge_time });
+    entity.health = Some(Health::new(tunables.enemy_health));
+    entity
 }
 
-pub fn make_bullet(player: &Entity, bullet_type: BulletType, fired_at: Vec2) -> Entity {
-    let bullet_ray = Ray::from_segment(&LineSegment::new(player.physics.position, fired_at));
-    let bullet_pos = bullet_ray.shape_intersection(&player.physics.collision_shape()).unwrap();
+// A wall that can be pushed around
+pub fn make_crate(tunables: &Tunables, size: f32, position: Vec2) -> Entity {
+    let mut entity = make_wall(size, size, position);
+    entity.renderable = Some(Renderable::new(Color::RGB(181, 137, 0), RenderStyle::Filled, BODY_LAYER));
+    entity.physics.material = Material::Wood;
+    entity.physics.category = CATEGORY_CRATE;
+    entity.physics.set_mass(tunables.crate_mass);
+    entity
+}
+
+// An invisible region that reports what goes in and out of it, for exits,
+// pickups and traps
+pub fn make_sensor(width: f32, height: f32, position: Vec2) -> Entity {
+    let shape = Shape::Rect { extent: Vec2::new(width, height) };
+
+    let mut entity = Entity::new(Physics::new(shape, position, Material::Stone, 0.0));
+    entity.physics.is_sensor = true;
+    entity.collider = Some(Collider);
+    entity
+}
+
+// Pushes everything inside the box the same way
+pub fn make_wind(width: f32, height: f32, position: Vec2, acceleration: Vec2) -> Entity {
+    let mut entity = make_sensor(width, height, position);
+    entity.force_field = Some(ForceField::Directional { acceleration });
+    entity
+}
+
+// Pulls everything inside the circle towards the middle and swirls it
+// around, a vortex with enough swirl
+pub fn make_gravity_well(radius: f32, position: Vec2, strength: f32, swirl: f32) -> Entity {
+    let mut entity = Entity::new(Physics::new(Shape::Circle { radius }, position, Material::Stone, 0.0));
+    entity.physics.is_sensor = true;
+    entity.collider = Some(Collider);
+    entity.force_field = Some(ForceField::Radial { strength, swirl });
+    entity
+}
+
+// Nothing when there's no way out of the shooter towards where it's fired,
+// like when it's fired at the shooter's own center
+pub fn make_bullet(tunables: &Tunables, owner: EntityId, shooter: &Entity, bullet_type: BulletType, fired_at: Vec2) -> Option<Entity> {
+    if fired_at == shooter.physics.position {
+        return None;
+    }
+
+    let bullet_ray = Ray::from_segment(&LineSegment::new(shooter.physics.position, fired_at));
+    let bullet_pos = bullet_ray.shape_intersection(&shooter.physics.collision_shape())?;
     let normal = bullet_ray.direction.normalize();
+    let position = bullet_pos + (normal * 0.0001);
 
     let (radius, velocity) = match bullet_type {
-        BulletType::PewPew => (2.0, 20.0),
-        BulletType::Boom => (4.0, 12.0)
+        BulletType::PewPew => (tunables.pew_pew_radius, tunables.pew_pew_speed),
+        BulletType::Boom => (tunables.boom_radius, tunables.boom_speed)
     };
 
-    Entity::new(
-        EntityType::Bullet(Bullet::new(bullet_type)),
-        Physics {
-            shape: Shape::Circle { radius: radius },
-            position: bullet_pos + (normal * 0.0001),
-            velocity: normal * velocity,
-            acceleration: vec2::ORIGIN,
+    let bullet = Bullet::new(bullet_type, owner);
 
-            restitution: 0.0,
-            inv_mass: 0.0
-        }
-    )
+    // Shots go through whoever is on the shooter's side
+    let (category, mask) = if shooter.player.is_some() {
+        (CATEGORY_PLAYER_BULLET, MASK_ALL & !CATEGORY_PLAYER)
+    } else {
+        (CATEGORY_ENEMY_BULLET, MASK_ALL & !CATEGORY_ENEMY)
+    };
+
+    let mut entity = Entity::new(Physics::new(Shape::Circle { radius }, position, Material::Metal, 0.0));
+    entity.physics.velocity = normal * velocity;
+    entity.physics.is_fast = true;
+    entity.physics.category = category;
+    entity.physics.mask = mask;
+    entity.renderable = Some(Renderable::new(bullet.color(), RenderStyle::Filled, BULLET_LAYER));
+    entity.bullet = Some(bullet);
+    Some(entity)
 }
 
 pub fn make_animation(color: Color, position: Vec2) -> Entity {
-    Entity::new(
-        EntityType::Animation(Animation::new(16, 250, color)),
-        Physics {
-            shape: Shape::Circle { radius: 0.0 },
-            position: position,
-            velocity: vec2::ORIGIN,
-            acceleration: vec2::ORIGIN,
+    let mut entity = Entity::new(Physics::new(Shape::Circle { radius: 0.0 }, position, Material::Metal, 0.0));
+    entity.renderable = Some(Renderable::new(color, RenderStyle::Burst { step_duration: 16 }, EFFECT_LAYER));
+    entity.lifetime = Some(Lifetime::new(250));
+    entity
+}
 
-            restitution: 0.0,
-            inv_mass: 0.0
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use level_file::read_level;
+
+    #[test]
+    fn shots_with_nowhere_to_go_are_skipped() {
+        let text = "size 800 600\nplayer 400 300\nturret 30 600 300";
+        let mut level = read_level(text.lines(), 1, Tunables::new()).unwrap();
+        let entity_count = level.iter().count();
+
+        level.apply_command(Command::QuickFire(Vec2::new(400.0, 300.0)));
+        assert_eq!(level.iter().count(), entity_count);
+
+        level.apply_command(Command::QuickFire(Vec2::new(500.0, 300.0)));
+        assert_eq!(level.iter().count(), entity_count + 1);
+
+        let (turret_id, turret) = level.iter().find(|&(_, entity)| entity.ai.is_some()).unwrap();
+        assert!(make_bullet(&level.tunables, turret_id, turret, BulletType::PewPew, Vec2::new(600.0, 300.0)).is_none());
+    }
+
+    #[test]
+    fn turrets_have_to_be_destroyed_to_clear_a_level() {
+        let text = "size 800 600\nplayer 100 300\nturret 30 400 300";
+        let mut level = read_level(text.lines(), 1, Tunables::new()).unwrap();
+        assert_eq!(level.status(), LevelStatus::Running);
+
+        for _ in 0..300 {
+            level.step(&[Command::QuickFire(Vec2::new(400.0, 300.0))], 10);
+            if level.status() != LevelStatus::Running {
+                break;
+            }
         }
-    )
+        assert_eq!(level.status(), LevelStatus::Cleared);
+    }
 }
-
@@ -0,0 +1,33 @@
+
+use vec2::Vec2;
+use collision::Manifold;
+use store::EntityId;
+
+// Things that happened during a Level::step. The level only keeps the
+// events of the last step, so anything interested has to look at them
+// after every step.
+#[derive(Debug, Copy, Clone)]
+pub enum GameEvent {
+    // The bullet is already gone from the level when this is seen.
+    // Damageable is whether the target has health or a shield.
+    BulletHit { bullet: EntityId, shooter: EntityId, target: EntityId, point: Vec2, damageable: bool },
+    Collision { a: EntityId, b: EntityId, manifold: Manifold },
+    ShieldSliceBroken { entity: EntityId, shooter: EntityId, slice: usize },
+    // Killer is whoever fired the bullet, nothing for crushes and contact damage
+    EntityDied { entity: EntityId, killer: Option<EntityId> },
+    // Sent every step something overlaps a sensor, enter on the first one
+    // and exit on the step after the last
+    SensorEnter { sensor: EntityId, other: EntityId },
+    SensorStay { sensor: EntityId, other: EntityId },
+    SensorExit { sensor: EntityId, other: EntityId }
+}
+
+pub trait EventListener {
+    fn handle_event(&mut self, event: &GameEvent);
+}
+
+pub fn dispatch(events: &[GameEvent], listener: &mut dyn EventListener) {
+    for event in events {
+        listener.handle_event(event);
+    }
+}
@@ -0,0 +1,34 @@
+use vec2::Vec2;
+
+// Pushes on whatever has its middle inside the entity's shape, bullets
+// included. Accelerations are in pixels per second squared, so light and
+// heavy bodies get pushed the same.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ForceField {
+    // The same push everywhere inside, like wind down a corridor
+    Directional { acceleration: Vec2 },
+    // Pulls towards the middle, or pushes away when strength is negative.
+    // Swirl turns things around the middle the way positive angles turn,
+    // the other way when negative.
+    Radial { strength: f32, swirl: f32 }
+}
+
+impl ForceField {
+    // Acceleration on something at position, for a field centered on center
+    pub fn acceleration(&self, center: Vec2, position: Vec2) -> Vec2 {
+        match *self {
+            ForceField::Directional { acceleration } => acceleration,
+            ForceField::Radial { strength, swirl } => {
+                let to_center = center - position;
+
+                // Right in the middle there's no way to pull
+                if to_center.magnitude_squared() == 0.0 {
+                    return Vec2::new(0.0, 0.0);
+                }
+
+                let inward = to_center.normalize();
+                inward * strength - inward.perpendicular() * swirl
+            }
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use sdl2::event::Event;
+use sdl2::render::WindowCanvas;
+
+use tunables::Tunables;
+
+pub enum Transition {
+    None,
+    Push(Box<dyn GameState>),
+    Pop,
+    Replace(Box<dyn GameState>),
+    // Throws away the whole stack and starts over from the given state
+    Reset(Box<dyn GameState>),
+    Quit
+}
+
+// One screen of the game. Only the state on top of the stack gets events
+// and updates, so anything underneath is frozen until it's on top again.
+pub trait GameState {
+    fn handle_event(&mut self, event: &Event) -> Transition;
+
+    // Called once per fixed tick
+    fn update(&mut self, time_delta: u32) -> Transition;
+
+    fn render(&self, canvas: &mut WindowCanvas, alpha: f32);
+
+    // Overlays are drawn on top of whatever state is below them
+    fn is_overlay(&self) -> bool {
+        false
+    }
+
+    // Every state hears about reloaded tunables, not just the one on top,
+    // so nothing goes back to the old values when it's uncovered
+    fn set_tunables(&mut self, _tunables: &Tunables) {}
+}
+
+pub struct StateStack {
+    states: Vec<Box<dyn GameState>>,
+    quit: bool
+}
+
+impl StateStack {
+    pub fn new(initial: Box<dyn GameState>) -> StateStack {
+        StateStack { states: vec![initial], quit: false }
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.quit && !self.states.is_empty()
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        let transition = match self.states.last_mut() {
+            Some(state) => state.handle_event(event),
+            None => Transition::None
+        };
+        self.apply(transition);
+    }
+
+    pub fn update(&mut self, time_delta: u32) {
+        let transition = match self.states.last_mut() {
+            Some(state) => state.update(time_delta),
+            None => Transition::None
+        };
+        self.apply(transition);
+    }
+
+    pub fn set_tunables(&mut self, tunables: &Tunables) {
+        for state in &mut self.states {
+            state.set_tunables(tunables);
+        }
+    }
+
+    pub fn render(&self, canvas: &mut WindowCanvas, alpha: f32) {
+        let mut first_visible = self.states.len();
+        while first_visible > 0 {
+            first_visible -= 1;
+            if !self.states[first_visible].is_overlay() {
+                break;
+            }
+        }
+
+        for state in &self.states[first_visible..] {
+            state.render(canvas, alpha);
+        }
+    }
+
+    fn apply(&mut self, transition: Transition) {
+        match transition {
+            Transition::None => {}
+            Transition::Push(state) => self.states.push(state),
+            Transition::Pop => {
+                self.states.pop();
+            }
+            Transition::Replace(state) => {
+                self.states.pop();
+                self.states.push(state);
+            }
+            Transition::Reset(state) => {
+                self.states.clear();
+                self.states.push(state);
+            }
+            Transition::Quit => self.quit = true
+        }
+    }
+}
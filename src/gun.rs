@@ -0,0 +1,69 @@
+
+use vec2;
+use vec2::Vec2;
+use bullet::BulletType;
+
+#[derive(Debug, Copy, Clone)]
+pub struct Gun {
+    // Where the gun is pointing, used to draw the charge at the muzzle
+    pub target: Vec2,
+    pub is_charging: bool,
+    pub charge_time: u32,
+    // Charging for longer than this fires a boom instead of a pew pew
+    pub boom_charge_time: u32
+}
+
+impl Gun {
+    pub fn new(boom_charge_time: u32) -> Gun {
+        Gun { target: vec2::ORIGIN, is_charging: false, charge_time: 0, boom_charge_time }
+    }
+
+    pub fn start_charging(&mut self) {
+        self.is_charging = true;
+        self.charge_time = 0;
+    }
+
+    pub fn cancel_charging(&mut self) {
+        self.is_charging = false;
+        self.charge_time = 0;
+    }
+
+    pub fn fire(&mut self) -> Option<BulletType> {
+        let bullet_type = self.bullet_type();
+
+        self.is_charging = false;
+        self.charge_time = 0;
+
+        bullet_type
+    }
+
+    pub fn update(&mut self, time_delta: u32) {
+        if self.is_charging {
+            self.charge_time += time_delta;
+        }
+    }
+
+    pub fn state(&self) -> Option<GunState> {
+        if !self.is_charging {
+            None
+        } else if self.charge_time < self.boom_charge_time {
+            Some(GunState::PewPew { charge: (self.charge_time as f32 / self.boom_charge_time as f32).min(1.0) })
+        } else {
+            Some(GunState::Boom { charge: ((self.charge_time - self.boom_charge_time) as f32 / 250.0).min(1.0) })
+        }
+    }
+
+    pub fn bullet_type(&self) -> Option<BulletType> {
+        match self.state() {
+            Some(GunState::PewPew {..}) => Some(BulletType::PewPew),
+            Some(GunState::Boom {..}) => Some(BulletType::Boom),
+            None => None
+        }
+    }
+
+}
+
+pub enum GunState {
+    PewPew { charge: f32 },
+    Boom { charge: f32 }
+}
@@ -0,0 +1,264 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+
+use vec2;
+use vec2::Vec2;
+use command::Command;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Fire,
+    QuickFire
+}
+
+impl Action {
+    pub fn from_name(name: &str) -> Option<Action> {
+        match name {
+            "move_up" => Some(Action::MoveUp),
+            "move_down" => Some(Action::MoveDown),
+            "move_left" => Some(Action::MoveLeft),
+            "move_right" => Some(Action::MoveRight),
+            "fire" => Some(Action::Fire),
+            "quick_fire" => Some(Action::QuickFire),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(Keycode),
+    Mouse(MouseButton)
+}
+
+impl Binding {
+    // Keys use SDL's key names ("W", "Up", "Left Shift"), mouse buttons are
+    // written as "Mouse Left", "Mouse Right", "Mouse Middle", "Mouse X1" or
+    // "Mouse X2".
+    pub fn from_name(name: &str) -> Option<Binding> {
+        if let Some(button) = name.strip_prefix("Mouse ") {
+            match button {
+                "Left" => Some(Binding::Mouse(MouseButton::Left)),
+                "Right" => Some(Binding::Mouse(MouseButton::Right)),
+                "Middle" => Some(Binding::Mouse(MouseButton::Middle)),
+                "X1" => Some(Binding::Mouse(MouseButton::X1)),
+                "X2" => Some(Binding::Mouse(MouseButton::X2)),
+                _ => None
+            }
+        } else {
+            Keycode::from_name(name).map(Binding::Key)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Bindings {
+    bindings: Vec<(Binding, Action)>
+}
+
+impl Default for Bindings {
+    fn default() -> Bindings {
+        Bindings::new()
+    }
+}
+
+impl Bindings {
+    pub fn new() -> Bindings {
+        Bindings {
+            bindings: vec![
+                (Binding::Key(Keycode::W), Action::MoveUp),
+                (Binding::Key(Keycode::Up), Action::MoveUp),
+                (Binding::Key(Keycode::S), Action::MoveDown),
+                (Binding::Key(Keycode::Down), Action::MoveDown),
+                (Binding::Key(Keycode::A), Action::MoveLeft),
+                (Binding::Key(Keycode::Left), Action::MoveLeft),
+                (Binding::Key(Keycode::D), Action::MoveRight),
+                (Binding::Key(Keycode::Right), Action::MoveRight),
+                (Binding::Mouse(MouseButton::Left), Action::Fire),
+                (Binding::Mouse(MouseButton::Right), Action::QuickFire)
+            ]
+        }
+    }
+
+    // Config lines look like "move_up = W, Up". Every action listed in the
+    // file replaces all of its default bindings, the rest keep theirs.
+    pub fn parse(config: &str) -> Result<Bindings, String> {
+        let mut bindings = Bindings::new();
+
+        for (line_number, line) in config.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let action_name = parts.next().unwrap().trim();
+            let binding_names = parts.next().ok_or(format!("Line {}: expected 'action = bindings'", line_number + 1))?;
+
+            let action = Action::from_name(action_name)
+                .ok_or(format!("Line {}: unknown action '{}'", line_number + 1, action_name))?;
+
+            bindings.bindings.retain(|&(_, a)| a != action);
+
+            for binding_name in binding_names.split(',').map(|name| name.trim()).filter(|name| !name.is_empty()) {
+                let binding = Binding::from_name(binding_name)
+                    .ok_or(format!("Line {}: unknown key or button '{}'", line_number + 1, binding_name))?;
+                bindings.bindings.push((binding, action));
+            }
+        }
+
+        Ok(bindings)
+    }
+
+    pub fn load(path: &Path) -> Result<Bindings, String> {
+        let mut contents = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut contents))
+            .map_err(|e| format!("Couldn't read controls {}: {}", path.display(), e))?;
+
+        Bindings::parse(&contents)
+    }
+
+    pub fn actions(&self, binding: Binding) -> Vec<Action> {
+        self.bindings.iter()
+            .filter(|&&(b, _)| b == binding)
+            .map(|&(_, action)| action)
+            .collect()
+    }
+}
+
+// Tracks which bindings are currently held and turns them into commands
+// once per tick. Movement is rebuilt from scratch every tick, so a lost key
+// release can't leave the player drifting.
+pub struct InputState {
+    bindings: Bindings,
+    held: HashSet<Binding>,
+    pending: Vec<Command>,
+    pub mouse_position: Vec2
+}
+
+impl InputState {
+    pub fn new(bindings: Bindings) -> InputState {
+        InputState {
+            bindings,
+            held: HashSet::new(),
+            pending: vec![],
+            mouse_position: vec2::ORIGIN
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::KeyDown { keycode: Some(keycode), repeat: false, .. } => {
+                self.press(Binding::Key(keycode));
+            }
+            Event::KeyUp { keycode: Some(keycode), .. } => {
+                self.release(Binding::Key(keycode));
+            }
+            Event::MouseButtonDown { mouse_btn, x, y, .. } => {
+                self.mouse_position = Vec2::from_ints(x, y);
+                self.press(Binding::Mouse(mouse_btn));
+            }
+            Event::MouseButtonUp { mouse_btn, x, y, .. } => {
+                self.mouse_position = Vec2::from_ints(x, y);
+                self.release(Binding::Mouse(mouse_btn));
+            }
+            Event::MouseMotion { x, y, .. } => {
+                self.mouse_position = Vec2::from_ints(x, y);
+            }
+            Event::Window { win_event: WindowEvent::FocusLost, .. } => {
+                self.release_all();
+            }
+            _ => {}
+        }
+    }
+
+    // Forgets everything held without the releases doing anything, so
+    // pausing with the fire button down doesn't fire. The charge is
+    // dropped instead.
+    pub fn release_all(&mut self) {
+        if self.is_held(Action::Fire) {
+            self.pending.push(Command::CancelGunCharging);
+        }
+
+        self.held.clear();
+    }
+
+    pub fn is_held(&self, action: Action) -> bool {
+        self.held.iter().any(|&binding| self.bindings.actions(binding).contains(&action))
+    }
+
+    // Commands for the next tick. Presses and releases since the last call
+    // are handed out once, movement and aim every time.
+    pub fn commands(&mut self, acceleration: f32) -> Vec<Command> {
+        let mut movement = vec2::ORIGIN;
+
+        if self.is_held(Action::MoveUp) { movement.y -= acceleration; }
+        if self.is_held(Action::MoveDown) { movement.y += acceleration; }
+        if self.is_held(Action::MoveLeft) { movement.x -= acceleration; }
+        if self.is_held(Action::MoveRight) { movement.x += acceleration; }
+
+        let mut commands = vec![Command::Move(movement), Command::LookAt(self.mouse_position)];
+        commands.append(&mut self.pending);
+        commands
+    }
+
+    fn press(&mut self, binding: Binding) {
+        if !self.held.insert(binding) {
+            return;
+        }
+
+        for action in self.bindings.actions(binding) {
+            match action {
+                Action::Fire => self.pending.push(Command::StartGunCharging),
+                Action::QuickFire => self.pending.push(Command::QuickFire(self.mouse_position)),
+                _ => {}
+            }
+        }
+    }
+
+    fn release(&mut self, binding: Binding) {
+        if !self.held.remove(&binding) {
+            return;
+        }
+
+        if self.bindings.actions(binding).contains(&Action::Fire) {
+            self.pending.push(Command::FireGun(self.mouse_position));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdl2::keyboard::NOMOD;
+
+    fn key_down(keycode: Keycode) -> Event {
+        Event::KeyDown { timestamp: 0, window_id: 0, keycode: Some(keycode), scancode: None, keymod: NOMOD, repeat: false }
+    }
+
+    #[test]
+    fn releasing_everything_cancels_the_charge_instead_of_firing() {
+        let mut input = InputState::new(Bindings { bindings: vec![(Binding::Key(Keycode::Space), Action::Fire)] });
+
+        input.handle_event(&key_down(Keycode::Space));
+        input.commands(0.0);
+
+        input.release_all();
+        let commands = input.commands(0.0);
+
+        assert!(!input.is_held(Action::Fire));
+        assert!(commands.iter().any(|command| matches!(*command, Command::CancelGunCharging)));
+        assert!(!commands.iter().any(|command| matches!(*command, Command::FireGun(_))));
+    }
+}
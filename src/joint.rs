@@ -0,0 +1,237 @@
+use vec2::Vec2;
+use entity::Physics;
+use solver::{inv_mass, effective_inv_mass, apply_impulse};
+use store::{EntityId, Id};
+use tunables::Tunables;
+
+// Anchors closer together than this have no direction to pull along
+const MIN_LENGTH: f32 = 0.001;
+
+// Handle to a joint in a level
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct JointId {
+    pub index: u32,
+    pub generation: u32
+}
+
+impl Id for JointId {
+    fn new(index: u32, generation: u32) -> JointId {
+        JointId { index, generation }
+    }
+
+    fn index(&self) -> u32 {
+        self.index
+    }
+
+    fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum JointKind {
+    // Keeps the anchors exactly this far apart, like a rod
+    Distance { length: f32 },
+    // Pulls the anchors back towards the rest length. Stiffness is how hard
+    // per pixel stretched and damping how hard per pixel per second, both
+    // per unit of mass.
+    Spring { rest_length: f32, stiffness: f32, damping: f32 },
+    // Only stops the anchors getting further apart than this, slack otherwise
+    Rope { max_length: f32 }
+}
+
+// Ties a point on one body to a point on another. Anchors are offsets from
+// each body's position and turn with it.
+#[derive(Debug, Copy, Clone)]
+pub struct Joint {
+    pub a: EntityId,
+    pub b: EntityId,
+    pub kind: JointKind,
+    pub a_anchor: Vec2,
+    pub b_anchor: Vec2,
+
+    // Built up over the step and kept for the next one, like contacts
+    impulse: f32,
+
+    is_active: bool,
+    a_offset: Vec2,
+    b_offset: Vec2,
+    // From a's anchor towards b's
+    axis: Vec2,
+    mass: f32,
+    // Softness and target speed that turn a rod into a spring
+    gamma: f32,
+    bias: f32
+}
+
+impl Joint {
+    pub fn new(a: EntityId, b: EntityId, kind: JointKind) -> Joint {
+        Joint {
+            a,
+            b,
+            kind,
+            a_anchor: Vec2::new(0.0, 0.0),
+            b_anchor: Vec2::new(0.0, 0.0),
+
+            impulse: 0.0,
+
+            is_active: false,
+            a_offset: Vec2::new(0.0, 0.0),
+            b_offset: Vec2::new(0.0, 0.0),
+            axis: Vec2::new(0.0, 0.0),
+            mass: 0.0,
+            gamma: 0.0,
+            bias: 0.0
+        }
+    }
+
+    pub fn distance(a: EntityId, b: EntityId, length: f32) -> Joint {
+        Joint::new(a, b, JointKind::Distance { length })
+    }
+
+    pub fn spring(a: EntityId, b: EntityId, rest_length: f32, stiffness: f32, damping: f32) -> Joint {
+        Joint::new(a, b, JointKind::Spring { rest_length, stiffness, damping })
+    }
+
+    pub fn rope(a: EntityId, b: EntityId, max_length: f32) -> Joint {
+        Joint::new(a, b, JointKind::Rope { max_length })
+    }
+
+    // What the solver starts the next step with, kept in replays so they
+    // pick up exactly where the recording did
+    pub fn impulse(&self) -> f32 {
+        self.impulse
+    }
+
+    pub fn set_impulse(&mut self, impulse: f32) {
+        self.impulse = impulse;
+    }
+
+    // Where the anchors are in the level
+    pub fn anchors(&self, a: &Physics, b: &Physics) -> (Vec2, Vec2) {
+        (a.position + self.a_anchor.rotate(a.angle), b.position + self.b_anchor.rotate(b.angle))
+    }
+
+    // Works out the direction and mass to pull with, then applies last
+    // step's impulse again. Slack ropes and springs with nothing to them
+    // sit the step out, and so do joints between two bodies that can't move.
+    pub fn prepare(&mut self, a: &mut Physics, b: &mut Physics, dt: f32) {
+        let (a_point, b_point) = self.anchors(a, b);
+        let between = b_point - a_point;
+        let length = between.magnitude();
+
+        self.a_offset = a_point - a.position;
+        self.b_offset = b_point - b.position;
+
+        self.is_active = length > MIN_LENGTH && inv_mass(a) + inv_mass(b) > 0.0 && match self.kind {
+            JointKind::Distance { .. } => true,
+            // A spring's softness comes from the step length, there's none in a step of 0
+            JointKind::Spring { stiffness, damping, .. } => dt > 0.0 && (stiffness > 0.0 || damping > 0.0),
+            JointKind::Rope { max_length } => length >= max_length
+        };
+
+        if !self.is_active {
+            self.impulse = 0.0;
+            return;
+        }
+
+        self.axis = between / length;
+
+        let inv_mass_sum = effective_inv_mass(a, b, self.a_offset, self.b_offset, self.axis);
+
+        // Springs are rods that give: the softness lets them stretch and
+        // the bias pulls them back at a speed set by how far they're out,
+        // which stays stable however stiff they get
+        match self.kind {
+            JointKind::Spring { rest_length, stiffness, damping } => {
+                let mass = 1.0 / (inv_mass(a) + inv_mass(b));
+                let (k, c) = (stiffness * mass, damping * mass);
+
+                self.gamma = 1.0 / (dt * (c + dt * k));
+                self.bias = (length - rest_length) * dt * k * self.gamma;
+            }
+            _ => {
+                self.gamma = 0.0;
+                self.bias = 0.0;
+            }
+        }
+
+        self.mass = 1.0 / (inv_mass_sum + self.gamma);
+
+        apply_impulse(a, b, self.a_offset, self.b_offset, self.axis * self.impulse);
+    }
+
+    pub fn solve_velocity(&mut self, a: &mut Physics, b: &mut Physics) {
+        if !self.is_active {
+            return;
+        }
+
+        let relative_velocity = b.velocity_at(self.b_offset) - a.velocity_at(self.a_offset);
+        let speed = relative_velocity.dot_product(self.axis);
+
+        let mut impulse = self.impulse - self.mass * (speed + self.bias + self.gamma * self.impulse);
+
+        // Ropes only ever pull
+        if let JointKind::Rope { .. } = self.kind {
+            impulse = impulse.min(0.0);
+        }
+
+        let change = impulse - self.impulse;
+        self.impulse = impulse;
+        apply_impulse(a, b, self.a_offset, self.b_offset, self.axis * change);
+    }
+
+    // Moves the bodies back to the right length. Springs are left to stretch.
+    pub fn solve_position(&self, a: &mut Physics, b: &mut Physics, tunables: &Tunables) {
+        let target = match self.kind {
+            JointKind::Distance { length } => length,
+            JointKind::Rope { max_length } => max_length,
+            JointKind::Spring { .. } => return
+        };
+
+        let (a_point, b_point) = self.anchors(a, b);
+        let between = b_point - a_point;
+        let length = between.magnitude();
+
+        if length <= MIN_LENGTH {
+            return;
+        }
+
+        let stretch = match self.kind {
+            JointKind::Rope { .. } => (length - target).max(0.0),
+            _ => length - target
+        };
+
+        let (a_inv_mass, b_inv_mass) = (inv_mass(a), inv_mass(b));
+        if a_inv_mass + b_inv_mass == 0.0 {
+            return;
+        }
+
+        let correction = stretch / (a_inv_mass + b_inv_mass) * tunables.correction_percent * (between / length);
+
+        a.position += a_inv_mass * correction;
+        b.position -= b_inv_mass * correction;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use level_file::read_level;
+    use tunables::Tunables;
+
+    #[test]
+    fn springs_survive_a_step_of_zero() {
+        let text = "size 800 600\nplayer 400 300\ncrate 20 100 100\ncrate 20 200 100\nspring_joint 1 2 80 40 2 0 0 0 0 0";
+        let mut level = read_level(text.lines(), 1, Tunables::new()).unwrap();
+
+        level.step(&[], 0);
+        level.step(&[], 10);
+
+        let (_, joint) = level.joints.iter().next().unwrap();
+        assert!(joint.impulse().is_finite());
+        for (_, entity) in level.iter() {
+            assert!(entity.physics.position.x.is_finite() && entity.physics.position.y.is_finite());
+            assert!(entity.physics.velocity.x.is_finite() && entity.physics.velocity.y.is_finite());
+        }
+    }
+}
@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use vec2::Vec2;
+use shape::{Shape, Polygon};
+use entity::{Level, Entity, make_player, make_wall, make_circle_wall, make_polygon_wall, make_enemy, make_turret, make_crate, make_sensor, make_door, make_moving_wall, make_wind, make_gravity_well};
+use force_field::ForceField;
+use joint::{Joint, JointKind};
+use mover::{Mover, PathMode, MAX_WAYPOINTS};
+use tunables::Tunables;
+
+// Plain text level description, one entity per line:
+//
+//     size 800 600
+//     player 400 300
+//     wall 40 40 200 200
+//     circle_wall 20 500 400
+//     polygon_wall 500 100 -30 20 30 20 0 -30
+//     crate 30 300 150
+//     turret 30 700 500
+//     enemy 600 200
+//     sensor 60 60 740 540
+//     door 20 80 400 100 400 20 120
+//     moving_wall 40 40 60 300 200 300 500
+//     wind 200 60 400 450 300 0
+//     gravity_well 80 600 400 500 200
+//     distance_joint 5 6 60 0 0 0 0 0
+//     spring_joint 5 7 80 40 2 0 0 10 0 0
+//     rope_joint 1 5 100 0 0 0 0 0
+//
+// Entities are listed in level order. Blank lines and lines starting with
+// '#' are ignored. Polygon walls give their position followed by at least
+// three vertices relative to it. Doors give their size, closed and open
+// positions and speed, moving walls their size and speed followed by the
+// waypoints they go back and forth along. Wind gives its size, position and
+// acceleration, gravity wells their radius, position, pull and swirl.
+// Joints come after the entities and give the two entities they join,
+// counting entity lines from 0, then the joint's lengths, stiffness and
+// damping, both anchors and the impulse it starts the next step with. How
+// big and heavy things are comes from the tunables the level is read with.
+
+pub fn write_level<W: Write>(level: &Level, out: &mut W) -> io::Result<()> {
+    writeln!(out, "size {} {}", level.width, level.height)?;
+
+    // Which entity line each entity ended up on, for the joints
+    let mut lines = HashMap::new();
+
+    for (id, entity) in level.collision_entities() {
+        write_entity(entity, out)?;
+        let line = lines.len();
+        lines.insert(id, line);
+    }
+
+    for (_, joint) in level.joints.iter() {
+        if let (Some(&a), Some(&b)) = (lines.get(&joint.a), lines.get(&joint.b)) {
+            match joint.kind {
+                JointKind::Distance { length } =>
+                    write!(out, "distance_joint {} {} {}", a, b, length)?,
+                JointKind::Spring { rest_length, stiffness, damping } =>
+                    write!(out, "spring_joint {} {} {} {} {}", a, b, rest_length, stiffness, damping)?,
+                JointKind::Rope { max_length } =>
+                    write!(out, "rope_joint {} {} {}", a, b, max_length)?
+            }
+            writeln!(out, " {} {} {} {} {}", joint.a_anchor.x, joint.a_anchor.y, joint.b_anchor.x, joint.b_anchor.y, joint.impulse())?;
+        }
+    }
+
+    Ok(())
+}
+
+// Fails for things no level line describes, like bullets in flight
+fn write_entity<W: Write>(entity: &Entity, out: &mut W) -> io::Result<()> {
+    let position = entity.physics.position;
+
+    match (archetype(entity), entity.physics.shape) {
+        (Some("player"), _) =>
+            writeln!(out, "player {} {}", position.x, position.y)?,
+        (Some("enemy"), _) =>
+            writeln!(out, "enemy {} {}", position.x, position.y)?,
+        (Some("wall"), Shape::Rect { extent }) =>
+            writeln!(out, "wall {} {} {} {}", extent.x, extent.y, position.x, position.y)?,
+        (Some("door"), Shape::Rect { extent }) => {
+            let mover = entity.mover.unwrap();
+            let (closed, open) = (mover.waypoints()[0], mover.waypoints()[1]);
+            writeln!(out, "door {} {} {} {} {} {} {}", extent.x, extent.y, closed.x, closed.y, open.x, open.y, mover.speed)?
+        }
+        (Some("moving_wall"), Shape::Rect { extent }) => {
+            let mover = entity.mover.unwrap();
+            write!(out, "moving_wall {} {} {}", extent.x, extent.y, mover.speed)?;
+            for waypoint in mover.waypoints() {
+                write!(out, " {} {}", waypoint.x, waypoint.y)?;
+            }
+            writeln!(out)?
+        }
+        (Some("wind"), Shape::Rect { extent }) => {
+            if let Some(ForceField::Directional { acceleration }) = entity.force_field {
+                writeln!(out, "wind {} {} {} {} {} {}", extent.x, extent.y, position.x, position.y, acceleration.x, acceleration.y)?
+            }
+        }
+        (Some("gravity_well"), Shape::Circle { radius }) => {
+            if let Some(ForceField::Radial { strength, swirl }) = entity.force_field {
+                writeln!(out, "gravity_well {} {} {} {} {}", radius, position.x, position.y, strength, swirl)?
+            }
+        }
+        (Some("sensor"), Shape::Rect { extent }) =>
+            writeln!(out, "sensor {} {} {} {}", extent.x, extent.y, position.x, position.y)?,
+        (Some("wall"), Shape::Circle { radius }) =>
+            writeln!(out, "circle_wall {} {} {}", radius, position.x, position.y)?,
+        (Some("wall"), Shape::Polygon(polygon)) => {
+            write!(out, "polygon_wall {} {}", position.x, position.y)?;
+            for vertex in polygon.vertices() {
+                write!(out, " {} {}", vertex.x, vertex.y)?;
+            }
+            writeln!(out)?
+        }
+        (Some(kind), Shape::Rect { extent }) =>
+            writeln!(out, "{} {} {} {}", kind, extent.x, position.x, position.y)?,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Entity has no level line"))
+    }
+
+    Ok(())
+}
+
+// Which of the make_* functions an entity most likely came from
+fn archetype(entity: &Entity) -> Option<&'static str> {
+    if let Some(field) = entity.force_field {
+        match field {
+            ForceField::Directional { .. } => Some("wind"),
+            ForceField::Radial { .. } => Some("gravity_well")
+        }
+    } else if entity.physics.is_sensor {
+        Some("sensor")
+    } else if let Some(mover) = entity.mover {
+        if mover.mode == PathMode::Stop { Some("door") } else { Some("moving_wall") }
+    } else if entity.player.is_some() {
+        Some("player")
+    } else if entity.ai.is_some() {
+        Some("turret")
+    } else if entity.shield.is_some() {
+        Some("enemy")
+    } else if entity.collider.is_some() && entity.physics.inv_mass == 0.0 {
+        Some("wall")
+    } else if entity.collider.is_some() {
+        Some("crate")
+    } else {
+        None
+    }
+}
+
+pub fn read_level<'a, I: Iterator<Item=&'a str>>(lines: I, seed: u64, tunables: Tunables) -> Result<Level, String> {
+    let mut level = None;
+    let mut ids = vec![];
+
+    for (line_number, line) in lines.enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut words = line.split_whitespace();
+        let kind = words.next().unwrap();
+        let args = words.map(|word| word.parse::<f32>())
+            .collect::<Result<Vec<f32>, _>>()
+            .map_err(|e| format!("Line {}: {}", line_number + 1, e))?;
+
+        let expected_args = match kind {
+            "size" | "player" | "enemy" => 2,
+            "circle_wall" | "crate" | "turret" => 3,
+            "wall" | "sensor" => 4,
+            "gravity_well" => 5,
+            "wind" => 6,
+            "door" => 7,
+            "distance_joint" | "rope_joint" => 8,
+            "spring_joint" => 10,
+            "moving_wall" if args.len() >= 7 && args.len() % 2 == 1 && args.len() <= 3 + 2 * MAX_WAYPOINTS => args.len(),
+            "moving_wall" =>
+                return Err(format!("Line {}: 'moving_wall' takes a size, a speed and 2 to {} waypoints", line_number + 1, MAX_WAYPOINTS)),
+            "polygon_wall" if args.len() >= 8 && args.len() % 2 == 0 => args.len(),
+            "polygon_wall" =>
+                return Err(format!("Line {}: 'polygon_wall' takes a position and at least 3 vertices", line_number + 1)),
+            _ => return Err(format!("Line {}: unknown entity '{}'", line_number + 1, kind))
+        };
+
+        if args.len() != expected_args {
+            return Err(format!("Line {}: '{}' takes {} numbers, got {}", line_number + 1, kind, expected_args, args.len()));
+        }
+
+        // How many of the numbers up front are sizes
+        let size_args = match kind {
+            "circle_wall" | "crate" | "turret" | "gravity_well" => 1,
+            "size" | "wall" | "sensor" | "door" | "moving_wall" | "wind" => 2,
+            _ => 0
+        };
+
+        if args[..size_args].iter().any(|&size| !size.is_finite() || size <= 0.0) {
+            return Err(format!("Line {}: '{}' needs a size above 0", line_number + 1, kind));
+        }
+
+        if kind == "size" {
+            if level.is_some() {
+                return Err(format!("Line {}: size given twice", line_number + 1));
+            }
+            level = Some(Level::empty(args[0], args[1], seed, tunables));
+            continue;
+        }
+
+        let level = level.as_mut().ok_or(format!("Line {}: size must come first", line_number + 1))?;
+
+        if kind == "player" && level.player_id.is_some() {
+            return Err(format!("Line {}: level already has a player", line_number + 1));
+        }
+
+        if kind.ends_with("_joint") {
+            let entity_at = |arg: f32| {
+                let id = if arg >= 0.0 && arg.fract() == 0.0 { ids.get(arg as usize).cloned() } else { None };
+                id.ok_or(format!("Line {}: no entity {} to join", line_number + 1, arg))
+            };
+            let (a, b) = (entity_at(args[0])?, entity_at(args[1])?);
+            if a == b {
+                return Err(format!("Line {}: can't join an entity to itself", line_number + 1));
+            }
+
+            let (joint_kind, rest) = match kind {
+                "distance_joint" => (JointKind::Distance { length: args[2] }, &args[3..]),
+                "rope_joint" => (JointKind::Rope { max_length: args[2] }, &args[3..]),
+                _ => (JointKind::Spring { rest_length: args[2], stiffness: args[3], damping: args[4] }, &args[5..])
+            };
+
+            let mut joint = Joint::new(a, b, joint_kind);
+            joint.a_anchor = Vec2::new(rest[0], rest[1]);
+            joint.b_anchor = Vec2::new(rest[2], rest[3]);
+            joint.set_impulse(rest[4]);
+            level.add_joint(joint);
+            continue;
+        }
+
+        let entity = match kind {
+            "player" => make_player(&tunables, Vec2::new(args[0], args[1])),
+            "enemy" => make_enemy(&tunables, Vec2::new(args[0], args[1])),
+            "circle_wall" => make_circle_wall(args[0], Vec2::new(args[1], args[2])),
+            "crate" => make_crate(&tunables, args[0], Vec2::new(args[1], args[2])),
+            "turret" => make_turret(&tunables, args[0], Vec2::new(args[1], args[2])),
+            "polygon_wall" => {
+                let vertices = args[2..].chunks(2).map(|xy| Vec2::new(xy[0], xy[1])).collect::<Vec<Vec2>>();
+                let polygon = Polygon::new(&vertices).map_err(|e| format!("Line {}: {}", line_number + 1, e))?;
+                make_polygon_wall(polygon, Vec2::new(args[0], args[1]))
+            }
+            "sensor" => make_sensor(args[0], args[1], Vec2::new(args[2], args[3])),
+            "wind" => make_wind(args[0], args[1], Vec2::new(args[2], args[3]), Vec2::new(args[4], args[5])),
+            "gravity_well" => make_gravity_well(args[0], Vec2::new(args[1], args[2]), args[3], args[4]),
+            "door" => make_door(args[0], args[1], Vec2::new(args[2], args[3]), Vec2::new(args[4], args[5]), args[6]),
+            "moving_wall" => {
+                let waypoints = args[3..].chunks(2).map(|xy| Vec2::new(xy[0], xy[1])).collect::<Vec<Vec2>>();
+                let mover = Mover::new(&waypoints, args[2], PathMode::PingPong).map_err(|e| format!("Line {}: {}", line_number + 1, e))?;
+                make_moving_wall(args[0], args[1], mover)
+            }
+            _ => make_wall(args[0], args[1], Vec2::new(args[2], args[3]))
+        };
+
+        ids.push(level.insert(entity));
+    }
+
+    match level {
+        Some(ref level) if level.player_id.is_none() => Err("Level has no player".to_string()),
+        Some(level) => Ok(level),
+        None => Err("Level has no size".to_string())
+    }
+}
+
+pub fn load_level(path: &Path, seed: u64, tunables: Tunables) -> Result<Level, String> {
+    let mut contents = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut contents))
+        .map_err(|e| format!("Couldn't read level {}: {}", path.display(), e))?;
+
+    read_level(contents.lines(), seed, tunables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(text: &str) -> Result<Level, String> {
+        read_level(text.lines(), 1, Tunables::new())
+    }
+
+    #[test]
+    fn sizes_must_be_above_zero() {
+        assert!(read("size 800 600\nplayer 400 300\nwall 0 40 405 300").is_err());
+        assert!(read("size 800 600\nplayer 400 300\nsensor 40 -1 100 100").is_err());
+        assert!(read("size 800 600\nplayer 400 300\ncrate 0 100 100").is_err());
+        assert!(read("size 800 600\nplayer 400 300\ndoor 20 0 400 100 400 20 120").is_err());
+        assert!(read("size 800 600\nplayer 400 300\nwall NaN 40 405 300").is_err());
+        assert!(read("size 0 600\nplayer 400 300").is_err());
+        assert!(read("size 800 600\nplayer 400 300\nwall 40 40 405 300").is_ok());
+    }
+
+    #[test]
+    fn joints_are_written_and_read_back() {
+        let level = read("size 800 600\nplayer 400 300\ncrate 20 100 100\ncrate 20 200 100\nspring_joint 1 2 80 40 2 5 0 -5 0 1.5").unwrap();
+
+        let mut out = vec![];
+        write_level(&level, &mut out).unwrap();
+        let level = read(&String::from_utf8(out).unwrap()).unwrap();
+
+        let (_, read_joint) = level.joints.iter().next().unwrap();
+        assert_eq!(read_joint.kind, JointKind::Spring { rest_length: 80.0, stiffness: 40.0, damping: 2.0 });
+        assert_eq!(read_joint.a_anchor, Vec2::new(5.0, 0.0));
+        assert_eq!(read_joint.b_anchor, Vec2::new(-5.0, 0.0));
+        assert_eq!(read_joint.impulse(), 1.5);
+        assert!(level.get(read_joint.a).is_some() && level.get(read_joint.b).is_some());
+    }
+
+    #[test]
+    fn joints_need_two_entities() {
+        assert!(read("size 800 600\nplayer 400 300\ndistance_joint 0 1 80 0 0 0 0 0").is_err());
+        assert!(read("size 800 600\nplayer 400 300\ncrate 20 100 100\nrope_joint 0 0.5 80 0 0 0 0 0").is_err());
+        assert!(read("size 800 600\nplayer 400 300\ncrate 20 100 100\nrope_joint 1 1 80 0 0 0 0 0").is_err());
+        assert!(read("size 800 600\nplayer 400 300\ncrate 20 100 100\nrope_joint 0 1 80 0 0 0 0 0").is_ok());
+    }
+}
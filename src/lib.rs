@@ -0,0 +1,35 @@
+#![allow(dead_code)]
+
+extern crate sdl2;
+
+pub mod render;
+pub mod vec2;
+pub mod entity;
+pub mod collision;
+pub mod line;
+pub mod shape;
+pub mod ray;
+pub mod components;
+pub mod player;
+pub mod shield;
+pub mod gun;
+pub mod ai;
+pub mod bullet;
+pub mod command;
+pub mod rng;
+pub mod level_file;
+pub mod replay;
+pub mod input;
+pub mod game_state;
+pub mod states;
+pub mod store;
+pub mod event;
+pub mod score;
+pub mod tunables;
+pub mod material;
+pub mod solver;
+pub mod query;
+pub mod mover;
+pub mod joint;
+pub mod force_field;
+pub mod dungeon;
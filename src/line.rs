@@ -1,70 +1,363 @@
-use std::f32;
-
 use vec2::Vec2;
+use shape::{AABB, Circle};
+
+// Segments turned less than this (the sine of the angle between them) are
+// parallel
+const PARALLEL_EPSILON: f32 = 0.00001;
 
-const INTERSECTION_EPSILON: f32 = 0.00003;
+// Slack on where along a segment a crossing is, as a fraction of its length,
+// so segments meeting end to end don't slip between each other
+const PARAMETER_EPSILON: f32 = 0.00001;
+
+// Points this close to a segment are on it
+const DISTANCE_EPSILON: f32 = 0.001;
 
 #[derive(Debug, Copy, Clone)]
-pub struct Line {
-    pub a: f32,
-    pub b: f32,
-    pub c: f32
+pub struct LineSegment {
+    pub start: Vec2,
+    pub end: Vec2
 }
 
-impl Line {
-    pub fn from_segment(segment: LineSegment) -> Line {
-        let a = segment.end.y - segment.start.y;
-        let b = segment.start.x - segment.end.x;
-        let c = a * segment.start.x + b * segment.start.y;
-        Line { a: a, b: b, c: c }
-    }
-
-    pub fn intersection(self, other: Line) -> Option<Vec2> {
-        let det = self.a * other.b - other.a * self.b;
-        if det != 0.0 {
-            let x = (other.b * self.c - self.b * other.c) / det;
-            let y = (self.a * other.c - other.a * self.c) / det;
+// Where two segments meet. t is how far along the first segment and u how
+// far along the second, 0 at the start and 1 at the end.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SegmentIntersection {
+    Point { point: Vec2, t: f32, u: f32 },
+    // Collinear segments share a stretch, from nearest to furthest along the
+    // first one
+    Overlap { start: Vec2, end: Vec2, t_start: f32, t_end: f32, u_start: f32, u_end: f32 }
+}
 
-            return Some(Vec2::new(x, y));
+impl SegmentIntersection {
+    // Where it first touches, going along the first segment
+    pub fn point(&self) -> Vec2 {
+        match *self {
+            SegmentIntersection::Point { point, .. } => point,
+            SegmentIntersection::Overlap { start, .. } => start
         }
+    }
 
-        return None;
+    pub fn t(&self) -> f32 {
+        match *self {
+            SegmentIntersection::Point { t, .. } => t,
+            SegmentIntersection::Overlap { t_start, .. } => t_start
+        }
     }
 }
 
-#[derive(Debug, Copy, Clone)]
-pub struct LineSegment {
-    pub start: Vec2,
-    pub end: Vec2
-}
+impl LineSegment {
 
-impl LineSegment { 
-    
     pub fn new(start: Vec2, end: Vec2) -> LineSegment {
-        LineSegment { start: start, end: end }
+        LineSegment { start, end }
+    }
+
+    pub fn direction(&self) -> Vec2 {
+        self.end - self.start
+    }
+
+    pub fn length(&self) -> f32 {
+        self.direction().magnitude()
+    }
+
+    pub fn point_at(&self, t: f32) -> Vec2 {
+        self.start + self.direction() * t
+    }
+
+    // How far along the segment the point nearest to the given one is
+    pub fn closest_t(&self, point: Vec2) -> f32 {
+        let d = self.direction();
+        let length_squared = d.magnitude_squared();
+        if length_squared == 0.0 {
+            return 0.0;
+        }
+
+        ((point - self.start).dot_product(d) / length_squared).clamp(0.0, 1.0)
+    }
+
+    pub fn closest_point(&self, point: Vec2) -> Vec2 {
+        self.point_at(self.closest_t(point))
+    }
+
+    pub fn distance_to_point(&self, point: Vec2) -> f32 {
+        point.distance(self.closest_point(point))
+    }
+
+    pub fn has_point(&self, point: Vec2) -> bool {
+        self.distance_to_point(point) <= DISTANCE_EPSILON
     }
 
-    pub fn has_point(self, point: Vec2) -> bool {
-        point.x >= self.start.x.min(self.end.x) - INTERSECTION_EPSILON && point.x <= self.start.x.max(self.end.x) + INTERSECTION_EPSILON
-            && point.y >= self.start.y.min(self.end.y) - INTERSECTION_EPSILON && point.y <= self.start.y.max(self.end.y) + INTERSECTION_EPSILON
+    // Where the lines through both segments cross, as (t, u) along each,
+    // without caring whether that's on the segments. None for parallel lines.
+    pub fn line_parameters(&self, other: &LineSegment) -> Option<(f32, f32)> {
+        let (d, e) = (self.direction(), other.direction());
+        let denominator = d.cross_product(e);
+
+        if denominator.abs() <= PARALLEL_EPSILON * d.magnitude() * e.magnitude() {
+            return None;
+        }
+
+        // start + t * d == other.start + u * e
+        let w = other.start - self.start;
+        Some((w.cross_product(e) / denominator, w.cross_product(d) / denominator))
     }
 
-    pub fn intersection(self, other: LineSegment) -> Option<Vec2> {
-        let line1 = Line::from_segment(self);
-        let line2 = Line::from_segment(other);
-        
-        line1.intersection(line2).and_then(|intersection| {
-            //println!("");
-            //println!("Line intersection: {:?}", intersection);
-            if self.has_point(intersection) && other.has_point(intersection) {
-                //println!("Segment intersection: {:?}", intersection);
-                return Some(intersection);
+    pub fn intersect(&self, other: &LineSegment) -> Option<SegmentIntersection> {
+        if self.direction().magnitude_squared() == 0.0 {
+            return if other.has_point(self.start) {
+                Some(SegmentIntersection::Point { point: self.start, t: 0.0, u: other.closest_t(self.start) })
             } else {
-                return None;
+                None
+            };
+        }
+
+        if other.direction().magnitude_squared() == 0.0 {
+            return if self.has_point(other.start) {
+                Some(SegmentIntersection::Point { point: other.start, t: self.closest_t(other.start), u: 0.0 })
+            } else {
+                None
+            };
+        }
+
+        match self.line_parameters(other) {
+            Some((t, u)) => {
+                let on_both = (-PARAMETER_EPSILON..=1.0 + PARAMETER_EPSILON).contains(&t) &&
+                    (-PARAMETER_EPSILON..=1.0 + PARAMETER_EPSILON).contains(&u);
+
+                if on_both {
+                    let t = t.clamp(0.0, 1.0);
+                    Some(SegmentIntersection::Point { point: self.point_at(t), t, u: u.clamp(0.0, 1.0) })
+                } else {
+                    None
+                }
             }
+            None => self.collinear_overlap(other)
+        }
+    }
+
+    // Just the point, for when it doesn't matter how the segments meet
+    pub fn intersection(&self, other: &LineSegment) -> Option<Vec2> {
+        self.intersect(other).map(|intersection| intersection.point())
+    }
+
+    // Parallel segments only meet if they lie on the same line, and then
+    // wherever their stretches along it overlap
+    fn collinear_overlap(&self, other: &LineSegment) -> Option<SegmentIntersection> {
+        let d = self.direction();
+        let off_line = (other.start - self.start).cross_product(d).abs() / d.magnitude();
+        if off_line > DISTANCE_EPSILON {
+            return None;
+        }
+
+        let length_squared = d.magnitude_squared();
+        let other_start = (other.start - self.start).dot_product(d) / length_squared;
+        let other_end = (other.end - self.start).dot_product(d) / length_squared;
+
+        let t_start = other_start.min(other_end).max(0.0);
+        let t_end = other_start.max(other_end).min(1.0);
+
+        if t_start > t_end + PARAMETER_EPSILON {
+            return None;
+        }
+
+        let start = self.point_at(t_start);
+        let u_start = other.closest_t(start);
+
+        if t_end - t_start <= PARAMETER_EPSILON {
+            return Some(SegmentIntersection::Point { point: start, t: t_start, u: u_start });
+        }
+
+        let end = self.point_at(t_end);
+
+        Some(SegmentIntersection::Overlap {
+            start,
+            end,
+            t_start,
+            t_end,
+            u_start,
+            u_end: other.closest_t(end)
         })
     }
 
+    // The stretch of the segment inside the circle, as (t entering, t leaving)
+    pub fn circle_intersection(&self, circle: &Circle) -> Option<(f32, f32)> {
+        let d = self.direction();
+        let f = self.start - circle.position;
+
+        let a = d.dot_product(d);
+        let c = f.dot_product(f) - circle.radius * circle.radius;
+
+        if a == 0.0 {
+            return if c <= 0.0 { Some((0.0, 0.0)) } else { None };
+        }
+
+        let b = 2.0 * f.dot_product(d);
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+
+        let root = discriminant.sqrt();
+        let enter = ((-b - root) / (2.0 * a)).max(0.0);
+        let leave = ((-b + root) / (2.0 * a)).min(1.0);
+
+        if enter <= leave { Some((enter, leave)) } else { None }
+    }
+
+    pub fn intersects_circle(&self, circle: &Circle) -> bool {
+        self.circle_intersection(circle).is_some()
+    }
+
+    // The stretch of the segment inside the box, as (t entering, t leaving)
+    pub fn aabb_intersection(&self, aabb: &AABB) -> Option<(f32, f32)> {
+        let d = self.direction();
+        let mut enter = 0.0f32;
+        let mut leave = 1.0f32;
+
+        let axes = [
+            (self.start.x, d.x, aabb.min.x, aabb.max.x),
+            (self.start.y, d.y, aabb.min.y, aabb.max.y)
+        ];
+
+        for &(start, delta, min, max) in &axes {
+            if delta == 0.0 {
+                if start < min || start > max {
+                    return None;
+                }
+                continue;
+            }
+
+            let t1 = (min - start) / delta;
+            let t2 = (max - start) / delta;
+
+            enter = enter.max(t1.min(t2));
+            leave = leave.min(t1.max(t2));
+
+            if enter > leave {
+                return None;
+            }
+        }
+
+        Some((enter, leave))
+    }
+
+    pub fn intersects_aabb(&self, aabb: &AABB) -> bool {
+        self.aabb_intersection(aabb).is_some()
+    }
+
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(x0: f32, y0: f32, x1: f32, y1: f32) -> LineSegment {
+        LineSegment::new(Vec2::new(x0, y0), Vec2::new(x1, y1))
+    }
+
+    #[test]
+    fn crossing_segments_meet_once() {
+        let intersection = segment(0.0, 0.0, 10.0, 10.0).intersect(&segment(0.0, 10.0, 10.0, 0.0));
+        assert_eq!(intersection, Some(SegmentIntersection::Point { point: Vec2::new(5.0, 5.0), t: 0.5, u: 0.5 }));
+
+        // The lines cross, but past the end of the second segment
+        assert_eq!(segment(0.0, 0.0, 10.0, 10.0).intersect(&segment(0.0, 10.0, 4.0, 6.0)), None);
+    }
 
+    #[test]
+    fn parallel_segments_never_meet() {
+        assert_eq!(segment(0.0, 0.0, 10.0, 0.0).intersect(&segment(0.0, 1.0, 10.0, 1.0)), None);
+    }
+
+    #[test]
+    fn collinear_segments_share_their_overlap() {
+        let intersection = segment(0.0, 0.0, 10.0, 0.0).intersect(&segment(15.0, 0.0, 5.0, 0.0));
+        assert_eq!(intersection, Some(SegmentIntersection::Overlap {
+            start: Vec2::new(5.0, 0.0),
+            end: Vec2::new(10.0, 0.0),
+            t_start: 0.5,
+            t_end: 1.0,
+            u_start: 1.0,
+            u_end: 0.5
+        }));
+    }
+
+    #[test]
+    fn collinear_segments_apart_never_meet() {
+        assert_eq!(segment(0.0, 0.0, 10.0, 0.0).intersect(&segment(11.0, 0.0, 20.0, 0.0)), None);
+    }
+
+    #[test]
+    fn segments_touching_at_the_ends_meet() {
+        // End to end along the same line
+        let intersection = segment(0.0, 0.0, 10.0, 0.0).intersect(&segment(10.0, 0.0, 20.0, 0.0));
+        assert_eq!(intersection, Some(SegmentIntersection::Point { point: Vec2::new(10.0, 0.0), t: 1.0, u: 0.0 }));
+
+        // Corner
+        let intersection = segment(0.0, 0.0, 10.0, 0.0).intersect(&segment(10.0, 0.0, 10.0, 10.0));
+        assert_eq!(intersection, Some(SegmentIntersection::Point { point: Vec2::new(10.0, 0.0), t: 1.0, u: 0.0 }));
+    }
+
+    #[test]
+    fn zero_length_segments_are_points() {
+        let point = segment(5.0, 0.0, 5.0, 0.0);
+
+        assert_eq!(point.intersect(&segment(0.0, 0.0, 10.0, 0.0)), Some(SegmentIntersection::Point { point: Vec2::new(5.0, 0.0), t: 0.0, u: 0.5 }));
+        assert_eq!(segment(0.0, 0.0, 10.0, 0.0).intersect(&point), Some(SegmentIntersection::Point { point: Vec2::new(5.0, 0.0), t: 0.5, u: 0.0 }));
+        assert_eq!(point.intersect(&segment(0.0, 1.0, 10.0, 1.0)), None);
+        assert_eq!(point.intersect(&point), Some(SegmentIntersection::Point { point: Vec2::new(5.0, 0.0), t: 0.0, u: 0.0 }));
+    }
+
+    #[test]
+    fn closest_points_stay_on_the_segment() {
+        let line = segment(0.0, 0.0, 10.0, 0.0);
+
+        assert_eq!(line.closest_t(Vec2::new(4.0, 3.0)), 0.4);
+        assert_eq!(line.closest_point(Vec2::new(4.0, 3.0)), Vec2::new(4.0, 0.0));
+        assert_eq!(line.distance_to_point(Vec2::new(4.0, 3.0)), 3.0);
+
+        // Past either end it's the end
+        assert_eq!(line.closest_t(Vec2::new(-5.0, 1.0)), 0.0);
+        assert_eq!(line.closest_point(Vec2::new(13.0, 4.0)), Vec2::new(10.0, 0.0));
+        assert_eq!(line.distance_to_point(Vec2::new(13.0, 4.0)), 5.0);
+
+        let point = segment(5.0, 5.0, 5.0, 5.0);
+        assert_eq!(point.closest_t(Vec2::new(8.0, 9.0)), 0.0);
+        assert_eq!(point.closest_point(Vec2::new(8.0, 9.0)), Vec2::new(5.0, 5.0));
+        assert_eq!(point.distance_to_point(Vec2::new(8.0, 9.0)), 5.0);
+    }
+
+    #[test]
+    fn circle_intersections_are_the_stretch_inside() {
+        let circle = Circle::new(Vec2::new(0.0, 0.0), 5.0);
+
+        assert_eq!(segment(-10.0, 0.0, 10.0, 0.0).circle_intersection(&circle), Some((0.25, 0.75)));
+        assert_eq!(segment(-10.0, 6.0, 10.0, 6.0).circle_intersection(&circle), None);
+        assert_eq!(segment(10.0, 0.0, 20.0, 0.0).circle_intersection(&circle), None);
+
+        // Starting inside, it's in from the start
+        assert_eq!(segment(0.0, 0.0, 20.0, 0.0).circle_intersection(&circle), Some((0.0, 0.25)));
+
+        assert_eq!(segment(1.0, 1.0, 1.0, 1.0).circle_intersection(&circle).map(|(enter, _)| enter), Some(0.0));
+        assert_eq!(segment(6.0, 0.0, 6.0, 0.0).circle_intersection(&circle), None);
+    }
+
+    #[test]
+    fn aabb_intersections_are_the_stretch_inside() {
+        let aabb = AABB::new(Vec2::new(0.0, 0.0), Vec2::new(10.0, 10.0));
+
+        assert_eq!(segment(-10.0, 5.0, 30.0, 5.0).aabb_intersection(&aabb), Some((0.25, 0.5)));
+        assert_eq!(segment(-10.0, -10.0, 30.0, 30.0).aabb_intersection(&aabb), Some((0.25, 0.5)));
+        assert_eq!(segment(20.0, 0.0, 30.0, 10.0).aabb_intersection(&aabb), None);
+
+        // Starting inside, it's in from the start
+        assert_eq!(segment(5.0, 5.0, 25.0, 5.0).aabb_intersection(&aabb), Some((0.0, 0.25)));
+
+        // Parallel to an edge, outside the box and then along the edge
+        assert_eq!(segment(-10.0, 12.0, 30.0, 12.0).aabb_intersection(&aabb), None);
+        assert_eq!(segment(-12.0, -10.0, -12.0, 30.0).aabb_intersection(&aabb), None);
+        assert_eq!(segment(-10.0, 10.0, 30.0, 10.0).aabb_intersection(&aabb), Some((0.25, 0.5)));
+
+        assert_eq!(segment(5.0, 5.0, 5.0, 5.0).aabb_intersection(&aabb).map(|(enter, _)| enter), Some(0.0));
+        assert_eq!(segment(15.0, 5.0, 15.0, 5.0).aabb_intersection(&aabb), None);
+    }
+}
@@ -1,219 +1,221 @@
-#![allow(dead_code)]
-
 extern crate sdl2;
+extern crate another_rogue;
+
+mod cli;
 
-mod render;
-mod vec2;
-mod entity;
-mod collision;
-mod line;
-mod shape;
-mod ray;
-mod animation;
-mod player;
-mod enemy;
-mod bullet;
-
-use std::f32;
-
-use sdl2::pixels::Color;
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
 use sdl2::gfx::framerate::FPSManager;
 
-use render::EntityRenderer;
-use entity::{Level, make_wall, make_circle_wall, make_bullet, make_animation, make_enemy};
-use collision::{collision_manifold, resolve_collision, nearest_ray_intersection, collision_point};
-use vec2::Vec2;
-use line::LineSegment;
-use ray::Ray;
-use entity::EntityType;
+use std::env;
+use std::path::Path;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-const WINDOW_WIDTH: f32 = 800.0;
-const WINDOW_HEIGHT: f32 = 600.0;
+use another_rogue::entity::Level;
+use another_rogue::input::Bindings;
+use another_rogue::game_state::{GameState, StateStack};
+use another_rogue::states::{GameSettings, TitleState, ReplayState, build_level};
+use another_rogue::replay::{Replay, checksum};
+use another_rogue::event::dispatch;
+use another_rogue::score::Score;
+use another_rogue::tunables::{Tunables, TunablesWatcher};
 
-const ACCELERATION: f32 = 1.0;
-const DRAG: f32 = 0.1;
+use cli::Options;
 
-const FPS: u32 = 60;
+// Physics runs in fixed steps regardless of how fast frames are drawn
+const TICK_MS: u32 = 10;
 
+// Longest frame we try to catch up on, so a stall doesn't snowball
+const MAX_FRAME_MS: u32 = 250;
 
-pub fn main() {
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
+const CONTROLS_PATH: &str = "controls.cfg";
 
-    let window = video_subsystem.window("rust-sdl2 demo: Video", 800 as u32, 600 as u32)
-        .position_centered()
-        .opengl()
-        .build()
-        .unwrap();
+// Re-read whenever it changes on disk
+const TUNABLES_PATH: &str = "tunables.cfg";
 
-    // let mut timer = sdl_context.timer().unwrap();
 
-    let mut canvas = window.into_canvas().present_vsync().build().unwrap();
+pub fn main() {
+    let options = match cli::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            process::exit(2);
+        }
+    };
 
-    let mut event_pump = sdl_context.event_pump().unwrap();
+    if options.help {
+        println!("{}", cli::USAGE);
+        return;
+    }
 
-    let mut fps_manager = FPSManager::new();
-    fps_manager.set_framerate(FPS).expect("Setting framerate didn't work");
+    let seed = options.seed.unwrap_or_else(|| {
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+    });
 
-    let mut level = Level::new(WINDOW_WIDTH, WINDOW_HEIGHT);
-    level.collision_entities.push(make_wall(40.0, 40.0, Vec2::new(200.0, 200.0)));
-    level.collision_entities.push(make_wall(40.0, 40.0, Vec2::new(400.0, 400.0)));
-    level.collision_entities.push(make_circle_wall(20.0, Vec2::new(500.0, 400.0)));
+    let replay = options.replay.as_ref().map(|path| {
+        Replay::load(path).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        })
+    });
 
-    level.collision_entities.push(make_enemy(Vec2::new(600.0, 200.0)));
+    if options.headless {
+        process::exit(run_headless(&options, seed, replay));
+    }
 
-    'running: loop {
-        let delta = fps_manager.delay();
-        //println!("Frame time delta: {}", delta);
+    run_window(&options, seed, replay);
+}
 
-        level.update(delta);
+fn settings(options: &Options, seed: u64, bindings: Bindings, tunables: Tunables) -> GameSettings {
+    GameSettings {
+        width: options.width as f32,
+        height: options.height as f32,
+        seed,
+        bindings,
+        tunables,
+        level_path: options.level.clone()
+    }
+}
 
-        for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit {..} | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
-                    break 'running
-                },
-                Event::KeyDown { keycode: Some(keycode), repeat, .. } => {
-                    //println!("KEYDOWN, repeat: {}", repeat);
-                    if !repeat {
-                        match keycode {
-                            Keycode::Right => {
-                                level.player_mut().physics.acceleration.x += ACCELERATION;
-                            },
-                            Keycode::Left => {
-                                level.player_mut().physics.acceleration.x -= ACCELERATION;
-                            },
-                            Keycode::Down => {
-                                level.player_mut().physics.acceleration.y += ACCELERATION;
-                            },
-                            Keycode::Up => {
-                                level.player_mut().physics.acceleration.y -= ACCELERATION;
-                            },
-                            _ => {}
-                        }
-                    }
-                }
-                Event::KeyUp { keycode: Some(keycode), .. } => {
-                    //println!("KEYUP");
-                    match keycode {
-                        Keycode::Right => {
-                            level.player_mut().physics.acceleration.x -= ACCELERATION;
-                        },
-                        Keycode::Left => {
-                            level.player_mut().physics.acceleration.x += ACCELERATION;
-                        },
-                        Keycode::Down => {
-                            level.player_mut().physics.acceleration.y -= ACCELERATION;
-                        },
-                        Keycode::Up => {
-                            level.player_mut().physics.acceleration.y += ACCELERATION;
-                        },
-                        _ => {}
-                    }
-                }
-                Event::MouseButtonDown {..} => {
-                    level.player_mut().player_mut().start_gun_charging();
+fn load_tunables() -> Tunables {
+    match Tunables::load(Path::new(TUNABLES_PATH)) {
+        Ok(tunables) => tunables,
+        Err(e) => {
+            println!("Using default tunables: {}", e);
+            Tunables::new()
+        }
+    }
+}
+
+// Runs the simulation without SDL and prints where it ended up, returning
+// the exit code
+fn run_headless(options: &Options, seed: u64, replay: Option<Replay>) -> i32 {
+    match replay {
+        Some(replay) => {
+            let ticks = replay.ticks.len();
+            match replay.run() {
+                Ok(level) => {
+                    println!("Replay of {} ticks matched the recording", ticks);
+                    print_summary(&level, None);
+                    0
                 }
-                Event::MouseButtonUp { x, y, .. } => {
-                    level.player_mut().player_mut().fire_gun().map(|bullet_type| {
-                        let bullet = make_bullet(level.player(), bullet_type, Vec2::from_ints(x, y));
-                        level.bullets.push(bullet)
-                    });
+                Err(desync) => {
+                    println!("Replay desynced at tick {}: expected {:016x}, got {:016x}",
+                             desync.tick, desync.expected, desync.actual);
+                    1
                 }
-                _ => {}
             }
         }
+        None => {
+            let mut level = build_level(&settings(options, seed, Bindings::new(), load_tunables()), 1);
+            let mut score = Score::new(level.player_id);
+            let ticks = options.ticks.unwrap_or(0);
+
+            for _ in 0..ticks {
+                level.step(&[], TICK_MS);
+                dispatch(&level.events, &mut score);
+            }
 
-
-        for entity in &mut level.collision_entities {
-            entity.physics.position += entity.physics.velocity;
+            println!("Ran {} ticks ({} ms) with seed {}", ticks, ticks as u64 * TICK_MS as u64, seed);
+            print_summary(&level, Some(&score));
+            0
         }
+    }
+}
 
-        for bullet in &mut level.bullets {
-            bullet.physics.position += bullet.physics.velocity;
-        }
+fn print_summary(level: &Level, score: Option<&Score>) {
+    println!("Status: {:?}", level.status());
+    println!("Entities: {}", level.entities.len());
 
-        // Entity collision
-        for i in 0..level.collision_entities.len() {
-            let (a, b) = level.collision_entities.split_at_mut(i + 1);
-            let entity_a = a.last_mut().unwrap();
-            for entity_b in b {
-                match collision_manifold(entity_a, entity_b) {
-                    Some(manifold) => {
-                        //println!("Collision manifold: {:?}", manifold);
-                        resolve_collision(entity_a, entity_b, manifold);
-                    }
-                    None => {}
-                }
-            }
-        }
+    if level.player_id.is_some() {
+        let player = level.player();
+        let health = player.health.map_or(0.0, |health| health.fraction());
+        println!("Player: position ({}, {}), health {}%",
+                 player.physics.position.x, player.physics.position.y, (health * 100.0).ceil());
+    }
 
-        // Bullet collision
-        {
-            let animations = &mut level.animations;
-            let collision_entities = &mut level.collision_entities;
-            level.bullets.retain(|bullet| {
-                match collision_point(bullet, collision_entities) {
-                    Some((index, point)) => {
-                        animations.push(make_animation(bullet.bullet().color(), point));
-
-                        let hit_entity = collision_entities.get_mut(index).unwrap();
-                        match hit_entity.entity_type {
-                            EntityType::Enemy(ref mut enemy) => {
-                                enemy.take_hit(&hit_entity.physics, bullet);
-                            }
-                            _ => {}
-                        }
-
-                        false
-                    }
-                    None => true
-                }
-            });
-        }
+    if let Some(score) = score {
+        println!("Score: {}", score.points);
+    }
 
-        for entity in &mut level.collision_entities {
-            entity.physics.velocity += entity.physics.acceleration - entity.physics.velocity * DRAG;
-        }
+    println!("Checksum: {:016x}", checksum(level));
+}
+
+fn run_window(options: &Options, seed: u64, replay: Option<Replay>) {
+    let sdl_context = sdl2::init().unwrap();
+    let video_subsystem = sdl_context.video().unwrap();
 
-        //println!("Player velocity: {:?}", level.player.velocity);
+    let mut window_builder = video_subsystem.window("rust-sdl2 demo: Video", options.width, options.height);
+    window_builder.position_centered().opengl();
 
+    if options.fullscreen {
+        window_builder.fullscreen();
+    }
 
-        level.animations.retain(|entity| {
-            !entity.animation().is_expired()
-        });
+    let window = window_builder.build().unwrap();
 
-        let mouse_state = event_pump.mouse_state();
-        let los_ray = Ray::from_segment(&LineSegment::new(level.player().physics.position, mouse_state.into()));
+    let mut canvas = window.into_canvas().present_vsync().build().unwrap();
 
-        let los_end = match nearest_ray_intersection(&los_ray, &level.non_player_collision_entities()) {
-            Some((_, p)) => p,
-            None => los_ray.origin + (800.0 * los_ray.direction).normalize()
-        };
+    let mut event_pump = sdl_context.event_pump().unwrap();
 
-        level.player_mut().player_mut().looking_at = los_end;
+    let mut tunables_watcher = TunablesWatcher::new(Path::new(TUNABLES_PATH));
+    let tunables = load_tunables();
 
-        canvas.set_draw_color(Color::RGB(88, 110, 117));
-        canvas.clear();
+    let mut fps_manager = FPSManager::new();
+    fps_manager.set_framerate(options.fps.unwrap_or(tunables.fps)).expect("Setting framerate didn't work");
 
-        for wall in &level.collision_entities {
-            canvas.draw_entity(wall);
+    let bindings = match Bindings::load(Path::new(CONTROLS_PATH)) {
+        Ok(bindings) => bindings,
+        Err(e) => {
+            println!("Using default controls: {}", e);
+            Bindings::new()
         }
+    };
+
+    let settings = settings(options, seed, bindings, tunables);
+
+    let first_state: Box<dyn GameState> = match replay {
+        Some(replay) => Box::new(ReplayState::new(settings, replay)),
+        None => Box::new(TitleState::new(settings))
+    };
 
-        for bullet in &level.bullets {
-            canvas.draw_entity(bullet);
+    let mut states = StateStack::new(first_state);
+
+    let mut accumulator = 0;
+
+    'running: while states.is_running() {
+        let delta = fps_manager.delay();
+
+        accumulator += delta.min(MAX_FRAME_MS);
+
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit {..} => break 'running,
+                _ => states.handle_event(&event)
+            }
         }
 
-        for entity in &level.animations {
-            canvas.draw_entity(entity);
+        match tunables_watcher.poll() {
+            Some(Ok(tunables)) => {
+                println!("Reloaded {}", TUNABLES_PATH);
+                if options.fps.is_none() {
+                    fps_manager.set_framerate(tunables.fps).expect("Setting framerate didn't work");
+                }
+                states.set_tunables(&tunables);
+            }
+            Some(Err(e)) => println!("Keeping the old tunables: {}", e),
+            None => {}
         }
 
-        canvas.present();
+        while accumulator >= TICK_MS {
+            states.update(TICK_MS);
+            accumulator -= TICK_MS;
+        }
 
+        let alpha = accumulator as f32 / TICK_MS as f32;
 
-        // println!("Ticks: {}", timer.ticks());
+        states.render(&mut canvas, alpha);
+
+        canvas.present();
     }
 }
-
@@ -0,0 +1,42 @@
+// What a body is made of. How each one bounces and slides comes from the
+// tunables, so changing them applies to everything already in the level.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Material {
+    Flesh,
+    Stone,
+    Rubber,
+    Wood,
+    Metal
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MaterialProperties {
+    // 0 stops dead, 1 bounces back just as fast
+    pub restitution: f32,
+    // Resting bodies stay put until pushed sideways harder than this times
+    // the push into the surface
+    pub static_friction: f32,
+    // Sliding bodies lose this times the push into the surface
+    pub dynamic_friction: f32
+}
+
+impl MaterialProperties {
+    // Restitution above 1 would add energy on every bounce
+    pub fn new(restitution: f32, static_friction: f32, dynamic_friction: f32) -> MaterialProperties {
+        MaterialProperties {
+            restitution: restitution.clamp(0.0, 1.0),
+            static_friction: static_friction.max(0.0),
+            dynamic_friction: dynamic_friction.max(0.0)
+        }
+    }
+
+    // How two materials behave against each other. The bouncier one wins so
+    // rubber bounces off anything, friction needs both to be rough.
+    pub fn combine(&self, other: &MaterialProperties) -> MaterialProperties {
+        MaterialProperties {
+            restitution: self.restitution.max(other.restitution),
+            static_friction: (self.static_friction * other.static_friction).sqrt(),
+            dynamic_friction: (self.dynamic_friction * other.dynamic_friction).sqrt()
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use vec2::Vec2;
+
+// Keeps Mover small enough to copy around with the rest of the entity
+pub const MAX_WAYPOINTS: usize = 8;
+
+// Closer than this counts as being at a waypoint
+const ARRIVAL_DISTANCE: f32 = 0.01;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PathMode {
+    // Back to the first waypoint after the last
+    Loop,
+    // Back and forth along the waypoints
+    PingPong,
+    // Waits at whichever waypoint it was sent to, like a door
+    Stop
+}
+
+// Drives a kinematic body from waypoint to waypoint at a steady speed.
+// Kinematic bodies push everything in their way and are never pushed back.
+#[derive(Debug, Copy, Clone)]
+pub struct Mover {
+    waypoints: [Vec2; MAX_WAYPOINTS],
+    waypoint_count: usize,
+    // Pixels per second
+    pub speed: f32,
+    pub mode: PathMode,
+    // Index of the waypoint it's heading for
+    pub target: usize,
+    // Going back down the waypoints when ping ponging
+    reversed: bool
+}
+
+impl Mover {
+    pub fn new(waypoints: &[Vec2], speed: f32, mode: PathMode) -> Result<Mover, String> {
+        if waypoints.is_empty() {
+            return Err("Path has no waypoints".to_string());
+        }
+        if waypoints.len() > MAX_WAYPOINTS {
+            return Err(format!("Path has more than {} waypoints", MAX_WAYPOINTS));
+        }
+
+        let mut mover = Mover {
+            waypoints: [Vec2::new(0.0, 0.0); MAX_WAYPOINTS],
+            waypoint_count: waypoints.len(),
+            speed,
+            mode,
+            target: 0,
+            reversed: false
+        };
+        mover.waypoints[..waypoints.len()].copy_from_slice(waypoints);
+        Ok(mover)
+    }
+
+    // Starts closed, slides to open when told to
+    pub fn door(closed: Vec2, open: Vec2, speed: f32) -> Mover {
+        Mover::new(&[closed, open], speed, PathMode::Stop).unwrap()
+    }
+
+    pub fn waypoints(&self) -> &[Vec2] {
+        &self.waypoints[..self.waypoint_count]
+    }
+
+    pub fn is_reversed(&self) -> bool {
+        self.reversed
+    }
+
+    pub fn go_to(&mut self, waypoint: usize) {
+        self.target = waypoint.min(self.waypoint_count - 1);
+    }
+
+    // Doors are open when heading for or at their last waypoint
+    pub fn is_open(&self) -> bool {
+        self.target == self.waypoint_count - 1
+    }
+
+    pub fn open(&mut self) {
+        let last = self.waypoint_count - 1;
+        self.go_to(last);
+    }
+
+    pub fn close(&mut self) {
+        self.go_to(0);
+    }
+
+    pub fn toggle(&mut self) {
+        if self.is_open() { self.close() } else { self.open() }
+    }
+
+    // Velocity that takes the body from where it is towards the target
+    // without overshooting it in the next dt seconds. Moves on to the next
+    // waypoint once it's there.
+    pub fn velocity(&mut self, position: Vec2, dt: f32) -> Vec2 {
+        // Every waypoint could be right here
+        for _ in 0..self.waypoint_count {
+            let to_target = self.waypoints[self.target] - position;
+            let distance = to_target.magnitude();
+
+            if distance > ARRIVAL_DISTANCE {
+                return if distance <= self.speed * dt {
+                    to_target / dt
+                } else {
+                    to_target * (self.speed / distance)
+                };
+            }
+
+            if !self.advance() {
+                break;
+            }
+        }
+
+        Vec2::new(0.0, 0.0)
+    }
+
+    // Picks the next waypoint, false if it's meant to wait where it is
+    fn advance(&mut self) -> bool {
+        if self.waypoint_count == 1 {
+            return false;
+        }
+
+        let last = self.waypoint_count - 1;
+
+        match self.mode {
+            PathMode::Stop => return false,
+            PathMode::Loop => self.target = (self.target + 1) % self.waypoint_count,
+            PathMode::PingPong => {
+                if self.target == last {
+                    self.reversed = true;
+                } else if self.target == 0 {
+                    self.reversed = false;
+                }
+                self.target = if self.reversed { self.target - 1 } else { self.target + 1 };
+            }
+        }
+
+        true
+    }
+}
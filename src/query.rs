@@ -0,0 +1,134 @@
+use vec2::Vec2;
+use ray::Ray;
+use shape::{AABB, Circle, CollisionShape};
+use collision::{TimeOfImpact, shape_collision_manifold, swept_circle_shape, swept_shape};
+use entity::{Entity, Level};
+use store::EntityId;
+
+// What's where in the level. Every query takes a filter saying which
+// entities count, hits come out nearest first and overlaps in level order.
+impl Level {
+
+    // Nearest collider the ray hits out of the ones the filter lets through
+    pub fn ray_cast<F>(&self, ray: &Ray, filter: F) -> Option<(EntityId, Vec2)>
+        where F: Fn(EntityId, &Entity) -> bool {
+
+        self.tree().ray_cast(ray, |id| {
+            self.entities.get(id)
+                .and_then(|entity| if filter(id, entity) { Some(entity) } else { None })
+                .and_then(|entity| ray.shape_intersection(&entity.physics.collision_shape()))
+                .map(|point| (ray.origin.distance(point), point))
+        })
+    }
+
+    // Every collider the ray hits within max_distance, nearest first, for
+    // shots that go through things
+    pub fn ray_cast_all<F>(&self, ray: &Ray, max_distance: f32, filter: F) -> Vec<(EntityId, Vec2)>
+        where F: Fn(EntityId, &Entity) -> bool {
+
+        let mut hits = vec![];
+
+        // Never reporting a hit makes the tree visit everything along the ray
+        self.tree().sweep(ray, 0.0, max_distance, |id| {
+            let hit = self.entities.get(id)
+                .and_then(|entity| if filter(id, entity) { Some(entity) } else { None })
+                .and_then(|entity| ray.shape_intersection(&entity.physics.collision_shape()));
+
+            if let Some(point) = hit {
+                let distance = ray.origin.distance(point);
+                if distance <= max_distance {
+                    hits.push((distance, id, point));
+                }
+            }
+
+            None::<(f32, ())>
+        });
+
+        hits.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap().then(a.1.cmp(&b.1)));
+        hits.into_iter().map(|(_, id, point)| (id, point)).collect()
+    }
+
+    // First collider a circle moving from `from` by `motion` would touch,
+    // out of the ones the filter lets through
+    pub fn sweep_circle<F>(&self, from: Vec2, radius: f32, motion: Vec2, filter: F) -> Option<(EntityId, TimeOfImpact)>
+        where F: Fn(EntityId, &Entity, &TimeOfImpact) -> bool {
+
+        let length = motion.magnitude();
+
+        self.tree().sweep(&Ray::new(from, motion), radius, length, |id| {
+            self.entities.get(id).and_then(|entity| {
+                swept_circle_shape(from, radius, motion, &entity.physics.collision_shape())
+                    .and_then(|impact| if filter(id, entity, &impact) { Some((impact.time * length, impact)) } else { None })
+            })
+        })
+    }
+
+    // First collider any shape moving by `motion` would touch, say to check
+    // whether something fits through a gap
+    pub fn shape_cast<F>(&self, shape: &CollisionShape, motion: Vec2, filter: F) -> Option<(EntityId, TimeOfImpact)>
+        where F: Fn(EntityId, &Entity, &TimeOfImpact) -> bool {
+
+        // The tree only knows about circles, so sweep one around the bounds
+        let bounds = shape.bounds();
+        let length = motion.magnitude();
+
+        self.tree().sweep(&Ray::new(bounds.position(), motion), bounds.half_extent().magnitude(), length, |id| {
+            self.entities.get(id).and_then(|entity| {
+                swept_shape(shape, motion, &entity.physics.collision_shape())
+                    .and_then(|impact| if filter(id, entity, &impact) { Some((impact.time * length, impact)) } else { None })
+            })
+        })
+    }
+
+    // Colliders whose bounds overlap the given ones, in level order
+    pub fn overlapping(&self, bounds: &AABB) -> Vec<EntityId> {
+        let mut ids = vec![];
+
+        self.tree().query(bounds, |id| {
+            let overlaps = self.entities.get(id).is_some_and(|entity| entity.physics.bounds().overlaps(bounds));
+            if overlaps {
+                ids.push(id);
+            }
+        });
+
+        ids.sort();
+        ids
+    }
+
+    // Colliders actually touching the shape, not just its bounds
+    pub fn overlap_shape<F>(&self, shape: &CollisionShape, filter: F) -> Vec<EntityId>
+        where F: Fn(EntityId, &Entity) -> bool {
+
+        self.overlapping(&shape.bounds()).into_iter()
+            .filter(|&id| {
+                let entity = self.entities.get(id).unwrap();
+                filter(id, entity) && shape_collision_manifold(shape, &entity.physics.collision_shape()).is_some()
+            })
+            .collect()
+    }
+
+    // Everything in a blast radius or within earshot
+    pub fn overlap_circle<F>(&self, center: Vec2, radius: f32, filter: F) -> Vec<EntityId>
+        where F: Fn(EntityId, &Entity) -> bool {
+
+        self.overlap_shape(&CollisionShape::Circle(Circle::new(center, radius)), filter)
+    }
+
+    pub fn overlap_aabb<F>(&self, aabb: &AABB, filter: F) -> Vec<EntityId>
+        where F: Fn(EntityId, &Entity) -> bool {
+
+        self.overlap_shape(&CollisionShape::AABB(*aabb), filter)
+    }
+
+    // Colliders under the point, for picking things with the mouse
+    pub fn entities_at<F>(&self, point: Vec2, filter: F) -> Vec<EntityId>
+        where F: Fn(EntityId, &Entity) -> bool {
+
+        self.overlapping(&AABB::new(point, point)).into_iter()
+            .filter(|&id| {
+                let entity = self.entities.get(id).unwrap();
+                filter(id, entity) && entity.physics.collision_shape().contains_point(point)
+            })
+            .collect()
+    }
+}
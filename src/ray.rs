@@ -2,7 +2,7 @@ use std::f32;
 
 use vec2::Vec2;
 use line::LineSegment;
-use shape::{CollisionShape, AABB, Circle};
+use shape::{CollisionShape, AABB, Circle, Polygon, Capsule};
 
 #[derive(Debug, Copy, Clone)]
 pub struct Ray {
@@ -13,7 +13,7 @@ pub struct Ray {
 impl Ray {
 
     pub fn new(origin: Vec2, direction: Vec2) -> Ray {
-        Ray { origin: origin, direction: direction }
+        Ray { origin, direction }
     }
 
     pub fn from_segment(segment: &LineSegment) -> Ray {
@@ -22,8 +22,10 @@ impl Ray {
 
     pub fn shape_intersection(&self, shape: &CollisionShape) -> Option<Vec2> {
         match shape {
-            &CollisionShape::AABB(ref aabb) => self.box_intersection(aabb),
-            &CollisionShape::Circle(ref circle) => self.circle_intersection(circle)
+            CollisionShape::AABB(aabb) => self.box_intersection(aabb),
+            CollisionShape::Circle(circle) => self.circle_intersection(circle),
+            CollisionShape::Polygon(polygon) => self.polygon_intersection(polygon),
+            CollisionShape::Capsule(capsule) => self.capsule_intersection(capsule)
         }
     }
 
@@ -38,6 +40,9 @@ impl Ray {
 
             tmin = tmin.max(tx1.min(tx2));
             tmax = tmax.min(tx1.max(tx2));
+        } else if self.origin.x < aabb.min.x || self.origin.x > aabb.max.x {
+            // Parallel to the box's sides and outside them
+            return None;
         }
 
         if self.direction.y != 0.0 {
@@ -46,6 +51,8 @@ impl Ray {
 
             tmin = tmin.max(ty1.min(ty2));
             tmax = tmax.min(ty1.max(ty2));
+        } else if self.origin.y < aabb.min.y || self.origin.y > aabb.max.y {
+            return None;
         }
 
         //println!("Ray: {:?}, tmin: {}, tmax: {}", self, tmin, tmax);
@@ -59,6 +66,94 @@ impl Ray {
         }
     }
 
+    // Nearest edge the ray crosses in front of its origin, so from inside
+    // it's where the ray leaves, same as boxes
+    pub fn polygon_intersection(&self, polygon: &Polygon) -> Option<Vec2> {
+        let mut nearest: Option<f32> = None;
+
+        let ray = LineSegment::new(self.origin, self.origin + self.direction);
+
+        for edge in polygon.edges() {
+            if let Some((t, u)) = ray.line_parameters(&edge) {
+                if t >= 0.0 && (0.0..=1.0).contains(&u) && nearest.is_none_or(|nearest| t < nearest) {
+                    nearest = Some(t);
+                }
+            }
+        }
+
+        nearest.map(|t| self.origin + (t * self.direction))
+    }
+
+    // Nearest of the two end circles and the box between them. From inside,
+    // where it comes out is where a ray coming back the other way goes in.
+    pub fn capsule_intersection(&self, capsule: &Capsule) -> Option<Vec2> {
+        if self.origin.distance(capsule.closest_point(self.origin)) <= capsule.radius {
+            let length = self.direction.magnitude();
+            if length == 0.0 {
+                return None;
+            }
+
+            let across = self.origin.distance(capsule.start) + capsule.start.distance(capsule.end) + 2.0 * capsule.radius;
+            let outside = Ray::new(self.origin + self.direction * (across / length), self.direction * -1.0);
+            return outside.capsule_intersection(capsule);
+        }
+
+        let mut hits = vec![
+            self.circle_intersection(&Circle::new(capsule.start, capsule.radius)),
+            self.circle_intersection(&Circle::new(capsule.end, capsule.radius))
+        ];
+
+        let side = (capsule.end - capsule.start).perpendicular();
+        if side.magnitude_squared() > 0.0 {
+            let offset = side.normalize() * capsule.radius;
+            let body = [capsule.start + offset, capsule.end + offset, capsule.end - offset, capsule.start - offset];
+
+            if let Ok(body) = Polygon::new(&body) {
+                hits.push(self.polygon_intersection(&body));
+            }
+        }
+
+        hits.into_iter()
+            .flatten()
+            .fold(None, |nearest: Option<Vec2>, hit| match nearest {
+                Some(nearest) if self.origin.distance(nearest) <= self.origin.distance(hit) => Some(nearest),
+                _ => Some(hit)
+            })
+    }
+
+    // How far along the ray it first touches the box, 0 if it starts inside.
+    // Nothing inside the box can be hit any closer than this.
+    pub fn box_entry_distance(&self, aabb: &AABB) -> Option<f32> {
+        let mut tmin = 0.0f32;
+        let mut tmax = f32::INFINITY;
+
+        if self.direction.x != 0.0 {
+            let tx1 = (aabb.min.x - self.origin.x) / self.direction.x;
+            let tx2 = (aabb.max.x - self.origin.x) / self.direction.x;
+
+            tmin = tmin.max(tx1.min(tx2));
+            tmax = tmax.min(tx1.max(tx2));
+        } else if self.origin.x < aabb.min.x || self.origin.x > aabb.max.x {
+            return None;
+        }
+
+        if self.direction.y != 0.0 {
+            let ty1 = (aabb.min.y - self.origin.y) / self.direction.y;
+            let ty2 = (aabb.max.y - self.origin.y) / self.direction.y;
+
+            tmin = tmin.max(ty1.min(ty2));
+            tmax = tmax.min(ty1.max(ty2));
+        } else if self.origin.y < aabb.min.y || self.origin.y > aabb.max.y {
+            return None;
+        }
+
+        if tmax >= tmin {
+            Some(tmin * self.direction.magnitude())
+        } else {
+            None
+        }
+    }
+
     pub fn circle_intersection(&self, circle: &Circle) -> Option<Vec2> {
         let d = self.direction;
         let f = self.origin - circle.position;
@@ -7,111 +7,182 @@ use sdl2::rect::{Rect, Point};
 use sdl2::mouse::MouseState;
 
 use vec2::Vec2;
-use entity::{Entity, EntityType, Physics};
+use entity::{Entity, Physics, Level};
 use ray::Ray;
 use line::LineSegment;
-use shape::Shape;
-use player::{Player, GunState};
-use enemy::Enemy;
-use animation::Animation;
+use shape::{Shape, CollisionShape};
+use player::Player;
+use gun::{Gun, GunState};
+use shield::Shield;
+use components::{Health, Lifetime, RenderStyle};
+use joint::Joint;
 
 
-impl Into<Point> for Vec2 {
-    fn into(self) -> Point {
-        Point::new(self.x as i32, self.y as i32)
+impl From<Vec2> for Point {
+    fn from(v: Vec2) -> Point {
+        Point::new(v.x as i32, v.y as i32)
     }
 }
 
-impl Into<Vec2> for MouseState {
-    fn into(self) -> Vec2 {
-        Vec2::new(self.x() as f32, self.y() as f32)
+impl From<MouseState> for Vec2 {
+    fn from(mouse: MouseState) -> Vec2 {
+        Vec2::new(mouse.x() as f32, mouse.y() as f32)
     }
 }
 
 pub trait EntityRenderer {
     fn draw_shape(&mut self, physics: &Physics, color: Color);
-    fn draw_player(&mut self, player: &Player, physics: &Physics);
-    fn draw_enemy(&mut self, enemy: &Enemy, physics: &Physics);
-    fn draw_animation(&mut self, animation: &Animation, physics: &Physics);
+    fn draw_line_of_sight(&mut self, player: &Player, physics: &Physics);
+    fn draw_gun(&mut self, gun: &Gun, physics: &Physics);
+    fn draw_shield(&mut self, shield: &Shield, physics: &Physics);
+    fn draw_core(&mut self, radius: f32, health: Option<&Health>, physics: &Physics, color: Color);
+    fn draw_burst(&mut self, step_duration: u32, lifetime: Option<&Lifetime>, physics: &Physics, color: Color);
     fn draw_entity(&mut self, entity: &Entity);
+    fn draw_joint(&mut self, joint: &Joint, a: &Physics, b: &Physics);
+    fn draw_level(&mut self, level: &Level, alpha: f32);
+    fn draw_text(&mut self, text: &str, center: Vec2, color: Color);
+}
+
+// Width and height of a character in the gfx built in font
+const FONT_SIZE: f32 = 8.0;
+
+fn fill_polygon(canvas: &mut WindowCanvas, vertices: &[Vec2], color: Color) -> Result<(), String> {
+    let xs = vertices.iter().map(|v| v.x.round() as i16).collect::<Vec<i16>>();
+    let ys = vertices.iter().map(|v| v.y.round() as i16).collect::<Vec<i16>>();
+    canvas.filled_polygon(&xs, &ys, color)
 }
 
 impl EntityRenderer for WindowCanvas {
 
+    // Drawn the way it collides, so turned boxes come out as polygons
     fn draw_shape(&mut self, physics: &Physics, color: Color) {
-        match physics.shape {
-            Shape::Rect { extent } => {
+        match physics.collision_shape() {
+            CollisionShape::AABB(aabb) => {
+                let extent = aabb.half_extent() * 2.0;
                 self.set_draw_color(color);
                 self.fill_rect(Rect::from_center(physics.position, extent.x as u32, extent.y as u32))
             }
-            Shape::Circle { radius } =>
-                self.filled_circle(physics.position.x.round() as i16, physics.position.y.round() as i16, radius.round() as i16, color)
+            CollisionShape::Circle(circle) =>
+                self.filled_circle(circle.position.x.round() as i16, circle.position.y.round() as i16, circle.radius.round() as i16, color),
+            CollisionShape::Polygon(polygon) => fill_polygon(self, polygon.vertices(), color),
+            CollisionShape::Capsule(capsule) => {
+                let side = (capsule.end - capsule.start).perpendicular();
+                if side.magnitude_squared() > 0.0 {
+                    let offset = side.normalize() * capsule.radius;
+                    fill_polygon(self, &[capsule.start + offset, capsule.end + offset, capsule.end - offset, capsule.start - offset], color)
+                        .expect("Draw didn't work");
+                }
+
+                self.filled_circle(capsule.start.x.round() as i16, capsule.start.y.round() as i16, capsule.radius.round() as i16, color)
+                    .and_then(|_| self.filled_circle(capsule.end.x.round() as i16, capsule.end.y.round() as i16, capsule.radius.round() as i16, color))
+            }
         }.expect("Draw didn't work")
     }
 
-    fn draw_player(&mut self, player: &Player, physics: &Physics) {
+    fn draw_line_of_sight(&mut self, player: &Player, physics: &Physics) {
         self.set_draw_color(Color::RGB(0, 0, 255));
         self.draw_line(physics.position, player.looking_at).expect("Draw didn't work");
+    }
 
-        self.draw_shape(physics, Color::RGB(0, 255, 0));
-
-        player.gun_state().map(|gun_state| {
-            let los_ray = Ray::from_segment(&LineSegment::new(physics.position, player.looking_at));
-            los_ray.shape_intersection(&physics.collision_shape()).map(|player_gun_intersection| {
+    fn draw_gun(&mut self, gun: &Gun, physics: &Physics) {
+        gun.state().map(|gun_state| {
+            let los_ray = Ray::from_segment(&LineSegment::new(physics.position, gun.target));
+            los_ray.shape_intersection(&physics.collision_shape()).map(|gun_intersection| {
                 let (radius, color) = match gun_state {
                     GunState::PewPew { charge } => (charge * 4.0, Color::RGB(255, 255, 0)),
                     GunState::Boom { charge } => ((charge * 2.0) + 4.0, Color::RGB(255, 0, 0))
                 };
 
-                self.filled_circle(player_gun_intersection.x.round() as i16, player_gun_intersection.y.round() as i16, radius.round() as i16, color).expect("Draw didn't work")
+                self.filled_circle(gun_intersection.x.round() as i16, gun_intersection.y.round() as i16, radius.round() as i16, color).expect("Draw didn't work")
             })
         });
     }
 
-    fn draw_enemy(&mut self, enemy: &Enemy, physics: &Physics) {
-        self.filled_circle(physics.position.x as i16, physics.position.y as i16, enemy.inner_radius as i16, Color::RGB(255, 0, 0)).expect("Draw didn't work");
-
-        match physics.shape {
-            Shape::Circle { radius } => {
-                let draw_radius = radius - 3.0;
-                let angle_step = (f32::consts::PI * 2.0) / enemy.shield_health.len() as f32;
+    fn draw_shield(&mut self, shield: &Shield, physics: &Physics) {
+        if let Shape::Circle { radius } = physics.shape {
+            let draw_radius = radius - 3.0;
+            let angle_step = (f32::consts::PI * 2.0) / shield.slice_count as f32;
 
-                for (i, shield_health) in enemy.shield_health.iter().enumerate() {
-                    if *shield_health > 0.0 {
-                        let angle = i as f32 * angle_step;
-                        let x = physics.position.x + draw_radius * angle.cos();
-                        let y = physics.position.y + draw_radius * angle.sin();
+            for (i, shield_health) in shield.slices().iter().enumerate() {
+                if *shield_health > 0.0 {
+                    let angle = i as f32 * angle_step;
+                    let x = physics.position.x + draw_radius * angle.cos();
+                    let y = physics.position.y + draw_radius * angle.sin();
 
-                        self.filled_circle(x.round() as i16, y.round() as i16, 3, Color::RGB(75, 162, 153)).expect("Draw didn't work");
-                    }
+                    self.filled_circle(x.round() as i16, y.round() as i16, 3, Color::RGB(75, 162, 153)).expect("Draw didn't work");
                 }
             }
-            _ => {}
         }
     }
 
-    fn draw_animation(&mut self, animation: &Animation, physics: &Physics) {
-        let size = 1 * animation.step();
-        self.filled_circle(physics.position.x as i16, physics.position.y as i16, (size / 2) as i16, animation.color).expect("Draw didn't work");
+    fn draw_core(&mut self, radius: f32, health: Option<&Health>, physics: &Physics, color: Color) {
+        let inner_radius = (radius * health.map_or(1.0, |health| health.fraction())).max(1.0);
+        self.filled_circle(physics.position.x as i16, physics.position.y as i16, inner_radius as i16, color).expect("Draw didn't work");
     }
 
+    fn draw_burst(&mut self, step_duration: u32, lifetime: Option<&Lifetime>, physics: &Physics, color: Color) {
+        let size = lifetime.map_or(0, |lifetime| lifetime.time_running / step_duration);
+        self.filled_circle(physics.position.x as i16, physics.position.y as i16, (size / 2) as i16, color).expect("Draw didn't work");
+    }
+
+    // Draws the entity's own look, then whatever its components add on top
     fn draw_entity(&mut self, entity: &Entity) {
-        match entity.entity_type {
-            EntityType::Player(ref player) => self.draw_player(player, &entity.physics),
-            EntityType::Enemy(ref enemy) => self.draw_enemy(enemy, &entity.physics),
-            EntityType::Animation(ref animation) => self.draw_animation(animation, &entity.physics),
-            _ => {
-               let color = match entity.entity_type {
-                   EntityType::Wall => Color::RGB(0, 0, 0),
-                   EntityType::Bullet(bullet) => bullet.color(),
-                   _ => panic!("wrong entity")
-               };
-
-               self.draw_shape(&entity.physics, color);
-            }
+        let renderable = match entity.renderable {
+            Some(renderable) => renderable,
+            None => return
         };
+
+        let physics = &entity.physics;
+
+        if let Some(ref player) = entity.player {
+            self.draw_line_of_sight(player, physics);
+        }
+
+        match renderable.style {
+            RenderStyle::Filled => self.draw_shape(physics, renderable.color),
+            RenderStyle::Core { radius } => self.draw_core(radius, entity.health.as_ref(), physics, renderable.color),
+            RenderStyle::Burst { step_duration } => self.draw_burst(step_duration, entity.lifetime.as_ref(), physics, renderable.color)
+        }
+
+        if let Some(ref shield) = entity.shield {
+            self.draw_shield(shield, physics);
+        }
+
+        if let Some(ref gun) = entity.gun {
+            self.draw_gun(gun, physics);
+        }
     }
 
-}
+    fn draw_joint(&mut self, joint: &Joint, a: &Physics, b: &Physics) {
+        let (a_point, b_point) = joint.anchors(a, b);
+        self.set_draw_color(Color::RGB(147, 161, 161));
+        self.draw_line(a_point, b_point).expect("Draw didn't work");
+    }
+
+    fn draw_level(&mut self, level: &Level, alpha: f32) {
+        let mut entities = level.iter()
+            .filter_map(|(_, entity)| entity.renderable.map(|renderable| (renderable.layer, entity)))
+            .collect::<Vec<(u8, &Entity)>>();
+
+        // Stable, so entities on the same layer keep level order
+        entities.sort_by_key(|&(layer, _)| layer);
 
+        for (_, entity) in entities {
+            self.draw_entity(&entity.interpolated(alpha));
+        }
+
+        // On top, so chains show across whatever they're tied to
+        for (_, joint) in level.joints.iter() {
+            if let (Some(a), Some(b)) = (level.get(joint.a), level.get(joint.b)) {
+                self.draw_joint(joint, &a.interpolated(alpha).physics, &b.interpolated(alpha).physics);
+            }
+        }
+    }
+
+    fn draw_text(&mut self, text: &str, center: Vec2, color: Color) {
+        let x = center.x - (text.len() as f32 * FONT_SIZE) / 2.0;
+        let y = center.y - FONT_SIZE / 2.0;
+        self.string(x.round() as i16, y.round() as i16, text, color).expect("Draw didn't work");
+    }
 
+}
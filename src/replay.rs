@@ -0,0 +1,443 @@
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write, BufWriter};
+use std::path::Path;
+
+use vec2::Vec2;
+use entity::{Level, Entity};
+use command::Command;
+use level_file::{write_level, read_level};
+use tunables::Tunables;
+
+// Replay files are plain text: the seed, the state of the level's
+// generator when recording started, the tunables (see tunables) and the
+// starting level (see level_file) each between a header line and "end",
+// then one line per tick with the tick's length in ms, the state checksum
+// after the tick and the commands that went into it. Tunables that were
+// reloaded during the run show up as another block before the tick they
+// were first used in.
+//
+//     seed 1234
+//     rng 1234
+//     tunables
+//     drag = 6
+//     end
+//     level
+//     size 800 600
+//     player 400 300
+//     end
+//     10 9a3f0c21d4e5b678 move 3600 0 look_at 120 80
+//     tunables
+//     drag = 4
+//     end
+//     10 1b2c3d4e5f607182 move 3600 0 look_at 121 80 charge
+
+pub struct Recorder<W: Write> {
+    out: W
+}
+
+impl Recorder<BufWriter<File>> {
+    pub fn create(path: &Path, level: &Level) -> io::Result<Recorder<BufWriter<File>>> {
+        Recorder::new(BufWriter::new(File::create(path)?), level)
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    // The level is saved as a level file, which only holds what a fresh
+    // level starts out with, so recording has to start before the first
+    // step and before anything is added, removed or moved by hand.
+    pub fn new(mut out: W, level: &Level) -> io::Result<Recorder<W>> {
+        let mut level_text = vec![];
+        write_level(level, &mut level_text)?;
+
+        if level.ticks > 0 || !reads_back_the_same(level, &String::from_utf8_lossy(&level_text)) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Replays have to start from a fresh level"));
+        }
+
+        writeln!(out, "seed {}", level.seed)?;
+        writeln!(out, "rng {}", level.rng.state)?;
+        writeln!(out, "tunables")?;
+        level.tunables.write(&mut out)?;
+        writeln!(out, "end")?;
+        writeln!(out, "level")?;
+        out.write_all(&level_text)?;
+        writeln!(out, "end")?;
+
+        Ok(Recorder { out })
+    }
+
+    // Call after each Level::step with the same commands and delta
+    pub fn record(&mut self, commands: &[Command], time_delta: u32, level: &Level) -> io::Result<()> {
+        write!(self.out, "{} {:016x}", time_delta, checksum(level))?;
+
+        for command in commands {
+            match *command {
+                Command::Move(v) => write!(self.out, " move {} {}", v.x, v.y)?,
+                Command::StartGunCharging => write!(self.out, " charge")?,
+                Command::FireGun(v) => write!(self.out, " fire {} {}", v.x, v.y)?,
+                Command::CancelGunCharging => write!(self.out, " cancel")?,
+                Command::QuickFire(v) => write!(self.out, " quick_fire {} {}", v.x, v.y)?,
+                Command::LookAt(v) => write!(self.out, " look_at {} {}", v.x, v.y)?
+            }
+        }
+
+        writeln!(self.out)
+    }
+
+    // Call when the level's tunables change, before the next record
+    pub fn record_tunables(&mut self, tunables: &Tunables) -> io::Result<()> {
+        writeln!(self.out, "tunables")?;
+        tunables.write(&mut self.out)?;
+        writeln!(self.out, "end")
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReplayTick {
+    pub time_delta: u32,
+    pub checksum: u64,
+    pub commands: Vec<Command>,
+    // Set when the tunables were reloaded just before this tick
+    pub tunables: Option<Tunables>
+}
+
+impl ReplayTick {
+    // Steps the level through this tick, handing back the level's checksum
+    // if it doesn't end up where the recording did
+    pub fn run(&self, level: &mut Level) -> Result<(), u64> {
+        if let Some(tunables) = self.tunables {
+            level.set_tunables(tunables);
+        }
+
+        level.step(&self.commands, self.time_delta);
+
+        let actual = checksum(level);
+        if actual == self.checksum {
+            Ok(())
+        } else {
+            Err(actual)
+        }
+    }
+}
+
+pub struct Replay {
+    pub seed: u64,
+    pub level: Level,
+    pub ticks: Vec<ReplayTick>
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Desync {
+    pub tick: usize,
+    pub expected: u64,
+    pub actual: u64
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Replay, String> {
+        let mut contents = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut contents))
+            .map_err(|e| format!("Couldn't read replay {}: {}", path.display(), e))?;
+
+        Replay::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Result<Replay, String> {
+        let mut lines = contents.lines();
+
+        let seed = lines.next()
+            .and_then(|line| parse_header(line, "seed"))
+            .ok_or("Replay doesn't start with a seed".to_string())?;
+
+        let rng_state = lines.next()
+            .and_then(|line| parse_header(line, "rng"))
+            .ok_or("Replay has no generator state".to_string())?;
+
+        if lines.next().map(|line| line.trim()) != Some("tunables") {
+            return Err("Replay has no tunables".to_string());
+        }
+
+        let tunables = parse_tunables(&mut lines)?;
+
+        if lines.next().map(|line| line.trim()) != Some("level") {
+            return Err("Replay has no level".to_string());
+        }
+
+        let mut level = read_level(lines.by_ref().take_while(|line| line.trim() != "end"), seed, tunables)?;
+        level.rng.state = rng_state;
+
+        let mut ticks = vec![];
+        let mut reloaded = None;
+        while let Some(line) = lines.next() {
+            if line.trim() == "tunables" {
+                reloaded = Some(parse_tunables(&mut lines)?);
+                continue;
+            }
+
+            let mut tick = parse_tick(line).ok_or(format!("Bad replay tick {}: '{}'", ticks.len(), line))?;
+            tick.tunables = reloaded.take();
+            ticks.push(tick);
+        }
+
+        Ok(Replay { seed, level, ticks })
+    }
+
+    // Re-runs the whole replay from the starting level, checking the state
+    // against the recording after every tick.
+    pub fn run(self) -> Result<Level, Desync> {
+        let mut level = self.level;
+
+        for (i, tick) in self.ticks.iter().enumerate() {
+            tick.run(&mut level).map_err(|actual| Desync { tick: i, expected: tick.checksum, actual })?;
+        }
+
+        Ok(level)
+    }
+}
+
+fn parse_header(line: &str, name: &str) -> Option<u64> {
+    let mut words = line.split_whitespace();
+    match (words.next(), words.next()) {
+        (Some(header), Some(value)) if header == name => value.parse::<u64>().ok(),
+        _ => None
+    }
+}
+
+fn parse_tunables<'a, I: Iterator<Item=&'a str>>(lines: &mut I) -> Result<Tunables, String> {
+    let config = lines.take_while(|line| line.trim() != "end").collect::<Vec<&str>>().join("\n");
+    Tunables::parse(&config).map_err(|e| format!("Bad replay tunables: {}", e))
+}
+
+fn parse_tick(line: &str) -> Option<ReplayTick> {
+    let mut words = line.split_whitespace();
+
+    let time_delta = words.next()?.parse::<u32>().ok()?;
+    let checksum = u64::from_str_radix(words.next()?, 16).ok()?;
+
+    let mut commands = vec![];
+    while let Some(word) = words.next() {
+        let command = match word {
+            "move" => Command::Move(parse_vec2(&mut words)?),
+            "charge" => Command::StartGunCharging,
+            "fire" => Command::FireGun(parse_vec2(&mut words)?),
+            "cancel" => Command::CancelGunCharging,
+            "quick_fire" => Command::QuickFire(parse_vec2(&mut words)?),
+            "look_at" => Command::LookAt(parse_vec2(&mut words)?),
+            _ => return None
+        };
+        commands.push(command);
+    }
+
+    Some(ReplayTick { time_delta, checksum, commands, tunables: None })
+}
+
+fn parse_vec2<'a, I: Iterator<Item=&'a str>>(words: &mut I) -> Option<Vec2> {
+    let x = words.next()?.parse::<f32>().ok()?;
+    let y = words.next()?.parse::<f32>().ok()?;
+    Some(Vec2::new(x, y))
+}
+
+fn reads_back_the_same(level: &Level, level_text: &str) -> bool {
+    match read_level(level_text.lines(), level.seed, level.tunables) {
+        Ok(mut read) => {
+            read.rng.state = level.rng.state;
+            checksum(&read) == checksum(level)
+        }
+        Err(_) => false
+    }
+}
+
+// FNV-1a over the exact bits of everything that evolves during a run
+pub fn checksum(level: &Level) -> u64 {
+    let mut hash = Fnv::new();
+
+    hash.write_u64(level.rng.state);
+    hash.write_u64(level.entities.len() as u64);
+
+    for (id, entity) in level.iter() {
+        hash.write_u64(((id.generation as u64) << 32) | id.index as u64);
+        hash_entity(&mut hash, entity);
+    }
+
+    hash.write_u64(level.joints.len() as u64);
+
+    for (id, joint) in level.joints.iter() {
+        hash.write_u64(((id.generation as u64) << 32) | id.index as u64);
+        hash.write_u64(((joint.a.generation as u64) << 32) | joint.a.index as u64);
+        hash.write_u64(((joint.b.generation as u64) << 32) | joint.b.index as u64);
+        hash.write_vec2(joint.a_anchor);
+        hash.write_vec2(joint.b_anchor);
+        hash.write_f32(joint.impulse());
+    }
+
+    hash.finish()
+}
+
+fn hash_entity(hash: &mut Fnv, entity: &Entity) {
+    let physics = &entity.physics;
+    hash.write_vec2(physics.position);
+    hash.write_vec2(physics.velocity);
+    hash.write_vec2(physics.acceleration);
+    hash.write_f32(physics.angle);
+    hash.write_f32(physics.angular_velocity);
+    hash.write_u64(physics.is_sleeping as u64);
+
+    if let Some(ref player) = entity.player {
+        hash.write_vec2(player.looking_at);
+    }
+
+    if let Some(ref gun) = entity.gun {
+        hash.write_u64(gun.charge_time as u64);
+    }
+
+    if let Some(ref health) = entity.health {
+        hash.write_f32(health.health);
+    }
+
+    if let Some(ref shield) = entity.shield {
+        for shield_health in shield.slices() {
+            hash.write_f32(*shield_health);
+        }
+    }
+
+    if let Some(ref lifetime) = entity.lifetime {
+        hash.write_u64(lifetime.time_running as u64);
+    }
+
+    if let Some(ref mover) = entity.mover {
+        hash.write_u64(mover.target as u64);
+        hash.write_u64(mover.is_reversed() as u64);
+    }
+}
+
+struct Fnv {
+    hash: u64
+}
+
+impl Fnv {
+    fn new() -> Fnv {
+        Fnv { hash: 0xcbf29ce484222325 }
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        for i in 0..8 {
+            self.hash ^= (value >> (i * 8)) & 0xff;
+            self.hash = self.hash.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn write_f32(&mut self, value: f32) {
+        self.write_u64(value.to_bits() as u64);
+    }
+
+    fn write_vec2(&mut self, value: Vec2) {
+        self.write_f32(value.x);
+        self.write_f32(value.y);
+    }
+
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dungeon::generate;
+    use level_file::read_level;
+
+    fn record(level: &mut Level, ticks: u32) -> String {
+        let mut recorder = Recorder::new(vec![], level).unwrap();
+        let commands = [Command::Move(Vec2::new(3600.0, 0.0))];
+
+        for _ in 0..ticks {
+            level.step(&commands, 10);
+            recorder.record(&commands, 10, level).unwrap();
+        }
+
+        String::from_utf8(recorder.out).unwrap()
+    }
+
+    #[test]
+    fn replays_with_joints_stay_in_sync() {
+        let text = "size 800 600\nplayer 100 100\ncrate 20 400 300\ncrate 20 460 300\ndistance_joint 1 2 80 0 0 0 0 0";
+        let mut level = read_level(text.lines(), 1, Tunables::new()).unwrap();
+
+        let replay = Replay::parse(&record(&mut level, 30)).unwrap();
+        assert_eq!(replay.level.joints.len(), 1);
+        assert!(replay.run().is_ok());
+    }
+
+    #[test]
+    fn recordings_play_back_in_sync() {
+        let mut level = generate(800.0, 600.0, 7, Tunables::new(), 3);
+        let mut recorder = Recorder::new(vec![], &level).unwrap();
+
+        let target = Vec2::new(400.0, 300.0);
+        for tick in 0..200 {
+            let mut commands = vec![Command::Move(Vec2::new(3600.0, -1800.0)), Command::LookAt(target)];
+            match tick % 50 {
+                0 => commands.push(Command::StartGunCharging),
+                20 => commands.push(Command::FireGun(target)),
+                30 => commands.push(Command::QuickFire(target)),
+                40 => commands.push(Command::CancelGunCharging),
+                _ => {}
+            }
+
+            if tick == 100 {
+                let mut tunables = level.tunables;
+                tunables.drag = 2.0;
+                level.set_tunables(tunables);
+                recorder.record_tunables(&tunables).unwrap();
+            }
+
+            level.step(&commands, 10);
+            recorder.record(&commands, 10, &level).unwrap();
+        }
+
+        let recording = String::from_utf8(recorder.out).unwrap();
+        let replay = Replay::parse(&recording).unwrap();
+        assert_eq!(replay.ticks.len(), 200);
+        assert_eq!(replay.ticks[100].tunables.map(|tunables| tunables.drag), Some(2.0));
+        assert_eq!(checksum(&replay.run().unwrap()), checksum(&level));
+
+        // Changing what happened on one tick shows up right there
+        let (at, _) = recording.match_indices("move 3600").nth(10).unwrap();
+        let tampered = format!("{}move -3600{}", &recording[..at], &recording[at + "move 3600".len()..]);
+        match Replay::parse(&tampered).unwrap().run() {
+            Err(desync) => assert_eq!(desync.tick, 10),
+            Ok(_) => panic!("Tampered replay stayed in sync")
+        }
+    }
+
+    #[test]
+    fn recording_needs_a_fresh_level() {
+        let text = "size 800 600\nplayer 100 100\ncrate 20 400 300\ncrate 20 460 300";
+        let fresh = || read_level(text.lines(), 1, Tunables::new()).unwrap();
+        assert!(Recorder::new(vec![], &fresh()).is_ok());
+
+        let mut stepped = fresh();
+        stepped.step(&[], 10);
+        assert!(Recorder::new(vec![], &stepped).is_err());
+
+        // Ids the level file can't give back
+        let mut removed = fresh();
+        let first_crate = removed.iter().map(|(id, _)| id).nth(1).unwrap();
+        removed.remove(first_crate);
+        assert!(Recorder::new(vec![], &removed).is_err());
+
+        let mut moving = fresh();
+        moving.player_mut().physics.velocity = Vec2::new(100.0, 0.0);
+        assert!(Recorder::new(vec![], &moving).is_err());
+
+        // Bullets have no level line
+        let mut shooting = fresh();
+        shooting.apply_command(Command::QuickFire(Vec2::new(400.0, 300.0)));
+        assert!(Recorder::new(vec![], &shooting).is_err());
+    }
+}
@@ -0,0 +1,90 @@
+
+use event::{GameEvent, EventListener};
+use store::EntityId;
+
+const POINTS_PER_HIT: u32 = 10;
+const POINTS_PER_SHIELD_SLICE: u32 = 25;
+const POINTS_PER_KILL: u32 = 100;
+
+pub struct Score {
+    pub points: u32,
+    // Only what the player's own bullets do counts
+    player_id: Option<EntityId>
+}
+
+impl Score {
+    pub fn new(player_id: Option<EntityId>) -> Score {
+        Score { points: 0, player_id }
+    }
+}
+
+impl EventListener for Score {
+    fn handle_event(&mut self, event: &GameEvent) {
+        match *event {
+            GameEvent::BulletHit { shooter, damageable: true, .. } if Some(shooter) == self.player_id => {
+                self.points += POINTS_PER_HIT;
+            }
+            GameEvent::ShieldSliceBroken { shooter, .. } if Some(shooter) == self.player_id => {
+                self.points += POINTS_PER_SHIELD_SLICE;
+            }
+            GameEvent::EntityDied { killer, .. } if killer.is_some() && killer == self.player_id => {
+                self.points += POINTS_PER_KILL;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entity::{make_bullet, Level};
+    use command::Command;
+    use event::dispatch;
+    use bullet::BulletType;
+    use level_file::read_level;
+    use tunables::Tunables;
+    use vec2::Vec2;
+
+    fn play(level: &mut Level, score: &mut Score, commands: &[Command], ticks: u32) -> Vec<GameEvent> {
+        let mut events = vec![];
+
+        for _ in 0..ticks {
+            level.step(commands, 10);
+            dispatch(&level.events, score);
+            events.extend(level.events.iter().cloned());
+        }
+
+        events
+    }
+
+    #[test]
+    fn player_hits_score() {
+        let text = "size 800 600\nplayer 100 300\nenemy 300 300";
+        let mut level = read_level(text.lines(), 1, Tunables::new()).unwrap();
+        let mut score = Score::new(level.player_id);
+
+        play(&mut level, &mut score, &[Command::QuickFire(Vec2::new(300.0, 300.0))], 1);
+        play(&mut level, &mut score, &[], 50);
+        assert!(score.points >= POINTS_PER_HIT);
+    }
+
+    #[test]
+    fn turret_hitting_a_wall_does_not_score() {
+        let text = "size 800 600\nplayer 100 300\nwall 20 200 400 300\nturret 30 700 300";
+        let mut level = read_level(text.lines(), 1, Tunables::new()).unwrap();
+        let mut score = Score::new(level.player_id);
+
+        let ids = level.iter().map(|(id, _)| id).collect::<Vec<EntityId>>();
+        let (wall_id, turret_id) = (ids[1], ids[2]);
+        let bullet = make_bullet(&Tunables::new(), turret_id, level.get(turret_id).unwrap(), BulletType::PewPew, Vec2::new(400.0, 300.0)).unwrap();
+        level.insert(bullet);
+
+        let events = play(&mut level, &mut score, &[], 100);
+        assert!(events.iter().any(|event| match *event {
+            GameEvent::BulletHit { shooter, target, .. } => shooter == turret_id && target == wall_id,
+            _ => false
+        }));
+        assert_eq!(score.points, 0);
+    }
+}
@@ -1,3 +1,5 @@
+use std::f32;
+
 use vec2::Vec2;
 use line::LineSegment;
 
@@ -10,7 +12,7 @@ pub struct AABB {
 impl AABB {
 
     pub fn new(min: Vec2, max: Vec2) -> AABB {
-        AABB { min: min, max: max }
+        AABB { min, max }
     }
 
     pub fn half_extent(&self) -> Vec2 {
@@ -21,6 +23,34 @@ impl AABB {
         self.min + self.half_extent()
     }
 
+    // Touching edges count as overlapping
+    pub fn overlaps(&self, other: &AABB) -> bool {
+        self.min.x <= other.max.x && other.min.x <= self.max.x &&
+            self.min.y <= other.max.y && other.min.y <= self.max.y
+    }
+
+    pub fn contains(&self, other: &AABB) -> bool {
+        self.min.x <= other.min.x && self.min.y <= other.min.y &&
+            other.max.x <= self.max.x && other.max.y <= self.max.y
+    }
+
+    pub fn union(&self, other: &AABB) -> AABB {
+        AABB::new(
+            Vec2::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y)),
+            Vec2::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y))
+        )
+    }
+
+    pub fn expanded(&self, margin: f32) -> AABB {
+        let margin = Vec2::new(margin, margin);
+        AABB::new(self.min - margin, self.max + margin)
+    }
+
+    // Stands in for area when deciding how to build bounding volume trees
+    pub fn perimeter(&self) -> f32 {
+        2.0 * ((self.max.x - self.min.x) + (self.max.y - self.min.y))
+    }
+
     pub fn line_segments(&self) -> [LineSegment; 4] {
         let top_right = Vec2::new(self.max.x, self.min.y);
         let bottom_left = Vec2::new(self.min.x, self.max.y);
@@ -42,19 +72,256 @@ pub struct Circle {
 
 impl Circle {
     pub fn new(position: Vec2, radius: f32) -> Circle {
-        Circle { position: position, radius: radius }
+        Circle { position, radius }
+    }
+}
+
+// Polygons keep their vertices inline so shapes stay Copy
+pub const MAX_POLYGON_VERTICES: usize = 8;
+
+// Convex polygon, vertices wound so that (edge.y, -edge.x) points out
+#[derive(Debug, Copy, Clone)]
+pub struct Polygon {
+    vertices: [Vec2; MAX_POLYGON_VERTICES],
+    vertex_count: usize
+}
+
+impl Polygon {
+    // Takes the corners in either winding order
+    pub fn new(points: &[Vec2]) -> Result<Polygon, String> {
+        if points.len() < 3 || points.len() > MAX_POLYGON_VERTICES {
+            return Err(format!("Polygons need 3 to {} corners, got {}", MAX_POLYGON_VERTICES, points.len()));
+        }
+
+        let mut vertices = [Vec2::new(0.0, 0.0); MAX_POLYGON_VERTICES];
+        vertices[..points.len()].copy_from_slice(points);
+
+        let mut polygon = Polygon { vertices, vertex_count: points.len() };
+
+        if polygon.signed_area() < 0.0 {
+            polygon.vertices[..points.len()].reverse();
+        }
+
+        let vertices = polygon.vertices();
+        for i in 0..vertices.len() {
+            let edge = vertices[(i + 1) % vertices.len()] - vertices[i];
+            let next_edge = vertices[(i + 2) % vertices.len()] - vertices[(i + 1) % vertices.len()];
+
+            if edge.magnitude_squared() == 0.0 {
+                return Err("Polygon has the same corner twice".to_string());
+            }
+            if edge.x * next_edge.y - edge.y * next_edge.x < 0.0 {
+                return Err("Polygon isn't convex".to_string());
+            }
+        }
+
+        if polygon.signed_area() == 0.0 {
+            return Err("Polygon has no area".to_string());
+        }
+
+        Ok(polygon)
+    }
+
+    // The box can't be flat, that would have no area
+    pub fn from_aabb(aabb: &AABB) -> Polygon {
+        Polygon::new(&[aabb.min, Vec2::new(aabb.max.x, aabb.min.y), aabb.max, Vec2::new(aabb.min.x, aabb.max.y)]).unwrap()
+    }
+
+    pub fn vertices(&self) -> &[Vec2] {
+        &self.vertices[..self.vertex_count]
+    }
+
+    // Turned around the origin
+    pub fn rotated(&self, angle: f32) -> Polygon {
+        let mut polygon = *self;
+        for vertex in &mut polygon.vertices[..self.vertex_count] {
+            *vertex = vertex.rotate(angle);
+        }
+        polygon
+    }
+
+    pub fn translated(&self, offset: Vec2) -> Polygon {
+        let mut polygon = *self;
+        for vertex in &mut polygon.vertices[..self.vertex_count] {
+            *vertex += offset;
+        }
+        polygon
+    }
+
+    pub fn edges(&self) -> Vec<LineSegment> {
+        let vertices = self.vertices();
+        (0..vertices.len())
+            .map(|i| LineSegment::new(vertices[i], vertices[(i + 1) % vertices.len()]))
+            .collect()
+    }
+
+    // Outward unit normal of each edge, in edge order
+    pub fn normals(&self) -> Vec<Vec2> {
+        self.edges().iter()
+            .map(|edge| {
+                let d = edge.end - edge.start;
+                Vec2::new(d.y, -d.x).normalize()
+            })
+            .collect()
+    }
+
+    pub fn center(&self) -> Vec2 {
+        let vertices = self.vertices();
+        let mut sum = Vec2::new(0.0, 0.0);
+        for vertex in vertices {
+            sum += *vertex;
+        }
+        sum / vertices.len() as f32
+    }
+
+    pub fn bounds(&self) -> AABB {
+        let vertices = self.vertices();
+        let mut bounds = AABB::new(vertices[0], vertices[0]);
+        for vertex in &vertices[1..] {
+            bounds = bounds.union(&AABB::new(*vertex, *vertex));
+        }
+        bounds
+    }
+
+    // Smallest and largest dot product of the vertices with the axis
+    pub fn project(&self, axis: Vec2) -> (f32, f32) {
+        let vertices = self.vertices();
+        let mut min = vertices[0].dot_product(axis);
+        let mut max = min;
+
+        for vertex in &vertices[1..] {
+            let p = vertex.dot_product(axis);
+            min = min.min(p);
+            max = max.max(p);
+        }
+
+        (min, max)
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        self.edges().iter().zip(self.normals())
+            .all(|(edge, normal)| (point - edge.start).dot_product(normal) <= 0.0)
+    }
+
+    fn signed_area(&self) -> f32 {
+        let vertices = self.vertices();
+        let mut area = 0.0;
+        for i in 0..vertices.len() {
+            let (a, b) = (vertices[i], vertices[(i + 1) % vertices.len()]);
+            area += a.x * b.y - b.x * a.y;
+        }
+        area / 2.0
+    }
+}
+
+// Everything within radius of the segment between start and end
+#[derive(Debug, Copy, Clone)]
+pub struct Capsule {
+    pub start: Vec2,
+    pub end: Vec2,
+    pub radius: f32
+}
+
+impl Capsule {
+    pub fn new(start: Vec2, end: Vec2, radius: f32) -> Capsule {
+        Capsule { start, end, radius }
+    }
+
+    // Point on the segment nearest to the given one
+    pub fn closest_point(&self, point: Vec2) -> Vec2 {
+        let d = self.end - self.start;
+        let length_squared = d.magnitude_squared();
+        if length_squared == 0.0 {
+            return self.start;
+        }
+
+        let t = ((point - self.start).dot_product(d) / length_squared).clamp(0.0, 1.0);
+        self.start + d * t
+    }
+
+    pub fn bounds(&self) -> AABB {
+        AABB::new(self.start, self.start)
+            .union(&AABB::new(self.end, self.end))
+            .expanded(self.radius)
     }
 }
 
 #[derive(Debug, Copy, Clone)]
 pub enum Shape {
     Rect { extent: Vec2 },
-    Circle { radius: f32 }
+    Circle { radius: f32 },
+    // Vertices relative to the position
+    Polygon(Polygon),
+    // Lies along the x axis before turning, centered on the position
+    Capsule { half_length: f32, radius: f32 }
+}
+
+impl Shape {
+    // Moment of inertia around the position for a body of uniform density
+    pub fn inertia(&self, mass: f32) -> f32 {
+        match *self {
+            Shape::Rect { extent } => mass * extent.magnitude_squared() / 12.0,
+            Shape::Circle { radius } => mass * radius * radius / 2.0,
+            Shape::Polygon(ref polygon) => {
+                let vertices = polygon.vertices();
+                let mut numerator = 0.0;
+                let mut denominator = 0.0;
+
+                // Sum of the triangles fanning out from the position
+                for i in 0..vertices.len() {
+                    let (a, b) = (vertices[i], vertices[(i + 1) % vertices.len()]);
+                    let cross = a.cross_product(b);
+                    numerator += cross * (a.dot_product(a) + a.dot_product(b) + b.dot_product(b));
+                    denominator += cross;
+                }
+
+                mass * numerator / (6.0 * denominator)
+            }
+            Shape::Capsule { half_length, radius } => {
+                // A box with a circle split across its ends, weighed by area
+                let box_area = 4.0 * half_length * radius;
+                let circle_area = f32::consts::PI * radius * radius;
+                let box_mass = mass * box_area / (box_area + circle_area);
+                let circle_mass = mass - box_mass;
+
+                box_mass * (half_length * half_length + radius * radius) / 3.0 +
+                    circle_mass * (radius * radius / 2.0 + half_length * half_length)
+            }
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
 pub enum CollisionShape {
     AABB(AABB),
-    Circle(Circle)
+    Circle(Circle),
+    Polygon(Polygon),
+    Capsule(Capsule)
 }
 
+
+impl CollisionShape {
+    // Smallest box around the shape
+    pub fn bounds(&self) -> AABB {
+        match *self {
+            CollisionShape::AABB(aabb) => aabb,
+            CollisionShape::Circle(circle) => {
+                let half_extent = Vec2::new(circle.radius, circle.radius);
+                AABB::new(circle.position - half_extent, circle.position + half_extent)
+            }
+            CollisionShape::Polygon(ref polygon) => polygon.bounds(),
+            CollisionShape::Capsule(ref capsule) => capsule.bounds()
+        }
+    }
+
+    // Points on the edge count as inside
+    pub fn contains_point(&self, point: Vec2) -> bool {
+        match *self {
+            CollisionShape::AABB(ref aabb) =>
+                point.x >= aabb.min.x && point.x <= aabb.max.x && point.y >= aabb.min.y && point.y <= aabb.max.y,
+            CollisionShape::Circle(ref circle) => point.distance(circle.position) <= circle.radius,
+            CollisionShape::Polygon(ref polygon) => polygon.contains(point),
+            CollisionShape::Capsule(ref capsule) => point.distance(capsule.closest_point(point)) <= capsule.radius
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::f32;
+
+use vec2::Vec2;
+use bullet::{Bullet, BulletType};
+
+// Shields keep their slices inline so entities stay Copy, this is the most
+// the shield_slices tunable can ask for
+pub const MAX_SHIELD_SLICES: usize = 32;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ShieldHit {
+    Blocked,
+    // Blocked, but the slice that took it is down now
+    Broken { slice: usize },
+    // The slice was already down, the hit goes through
+    Through
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Shield {
+    pub shield_health: [f32; MAX_SHIELD_SLICES],
+    pub slice_count: usize
+}
+
+impl Shield {
+    pub fn new(slice_count: u32) -> Shield {
+        Shield {
+            shield_health: [1.0; MAX_SHIELD_SLICES],
+            slice_count: (slice_count as usize).clamp(1, MAX_SHIELD_SLICES)
+        }
+    }
+
+    pub fn slices(&self) -> &[f32] {
+        &self.shield_health[..self.slice_count]
+    }
+
+    // Boom bullets knock down the shield slice they hit. Anything that gets
+    // through a slice that is already down should hurt whatever the shield
+    // protects.
+    pub fn take_hit(&mut self, center: Vec2, bullet_position: Vec2, bullet: &Bullet, boom_damage: f32) -> ShieldHit {
+        let pos = bullet_position - center;
+        let mut angle = pos.y.atan2(pos.x);
+        if angle < 0.0 {
+            angle += 2.0 * f32::consts::PI;
+        }
+
+        // Angles just short of a full turn round up to the first slice again
+        let shield_slice = ((angle / (2.0 * f32::consts::PI)) * self.slice_count as f32).round() as usize % self.slice_count;
+
+        if self.shield_health[shield_slice] > 0.0 {
+            if bullet.bullet_type == BulletType::Boom {
+                self.shield_health[shield_slice] -= boom_damage;
+
+                if self.shield_health[shield_slice] <= 0.0 {
+                    return ShieldHit::Broken { slice: shield_slice };
+                }
+            }
+            ShieldHit::Blocked
+        } else {
+            ShieldHit::Through
+        }
+    }
+
+    // Slices regenerate at regen shield health per second
+    pub fn update(&mut self, time_delta: u32, regen: f32) {
+        for shield_health in &mut self.shield_health[..self.slice_count] {
+            *shield_health = (*shield_health + regen * time_delta as f32 / 1000.0).min(1.0);
+        }
+
+    }
+
+}
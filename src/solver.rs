@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+
+use vec2::Vec2;
+use entity::{Entity, Physics};
+use collision::{Manifold, collision_manifold};
+use joint::{Joint, JointId};
+use store::{Store, EntityId};
+use tunables::Tunables;
+
+// Bumps slower than this (pixels per second) don't bounce, so bodies
+// pressed together stay put instead of rattling
+const RESTITUTION_THRESHOLD: f32 = 20.0;
+
+// Contacts sliding slower than this use static friction
+const STATIC_FRICTION_THRESHOLD: f32 = 5.0;
+
+// Contacts whose normal turned more than this since the last step start
+// from nothing instead of last step's impulses
+const WARM_START_MIN_DOT: f32 = 0.9;
+
+// One touching pair, with the impulses the solver has built up on it so far
+#[derive(Debug, Copy, Clone)]
+pub struct Contact {
+    pub a: EntityId,
+    pub b: EntityId,
+    pub manifold: Manifold,
+
+    normal_impulse: f32,
+    tangent_impulse: f32,
+
+    a_offset: Vec2,
+    b_offset: Vec2,
+    normal_mass: f32,
+    tangent_mass: f32,
+    // Separating speed restitution aims for
+    velocity_bias: f32,
+    friction: f32
+}
+
+impl Contact {
+    pub fn new(a: EntityId, b: EntityId, manifold: Manifold) -> Contact {
+        Contact {
+            a,
+            b,
+            manifold,
+
+            normal_impulse: 0.0,
+            tangent_impulse: 0.0,
+
+            a_offset: Vec2::new(0.0, 0.0),
+            b_offset: Vec2::new(0.0, 0.0),
+            normal_mass: 0.0,
+            tangent_mass: 0.0,
+            velocity_bias: 0.0,
+            friction: 0.0
+        }
+    }
+
+    fn tangent(&self) -> Vec2 {
+        self.manifold.normal.perpendicular()
+    }
+}
+
+// Solves every contact and joint of a step together, a few times over, so
+// pushes travel through piles of bodies and along chains instead of
+// fighting each other pair by pair. Impulses are kept between steps to
+// start the next solve from.
+pub struct ContactSolver {
+    // Normal impulse, tangent impulse and normal of last step's contacts
+    cache: HashMap<(EntityId, EntityId), (f32, f32, Vec2)>
+}
+
+impl Default for ContactSolver {
+    fn default() -> ContactSolver {
+        ContactSolver::new()
+    }
+}
+
+impl ContactSolver {
+    pub fn new() -> ContactSolver {
+        ContactSolver { cache: HashMap::new() }
+    }
+
+    pub fn solve(&mut self, entities: &mut Store<Entity>, mut contacts: Vec<Contact>, joints: &mut Store<Joint, JointId>, dt: f32, tunables: &Tunables) {
+        let touching = contacts.iter()
+            .map(|contact| (contact.a, contact.b))
+            .chain(joints.iter().map(|(_, joint)| (joint.a, joint.b)))
+            .collect::<Vec<(EntityId, EntityId)>>();
+        wake_touched(entities, &touching, tunables);
+
+        contacts.retain(|contact| {
+            let (a, b) = (&entities.get(contact.a).unwrap().physics, &entities.get(contact.b).unwrap().physics);
+            inv_mass(a) + inv_mass(b) > 0.0
+        });
+
+        // Walls go last so they get the final say, and something pinned
+        // against one by a moving wall is squashed into the moving wall
+        // instead of pushed out through the other side
+        contacts.sort_by_key(|contact| {
+            let (a, b) = (&entities.get(contact.a).unwrap().physics, &entities.get(contact.b).unwrap().physics);
+            is_wall(a) || is_wall(b)
+        });
+
+        for contact in contacts.iter_mut() {
+            self.prepare(entities, contact, tunables);
+        }
+
+        for (_, joint) in joints.iter_mut() {
+            if let Some((a, b)) = entities.get_pair_mut(joint.a, joint.b) {
+                joint.prepare(&mut a.physics, &mut b.physics, dt);
+            }
+        }
+
+        // Joints before contacts, so a chain can't drag anything through a wall
+        for _ in 0..tunables.solver_iterations {
+            for (_, joint) in joints.iter_mut() {
+                if let Some((a, b)) = entities.get_pair_mut(joint.a, joint.b) {
+                    joint.solve_velocity(&mut a.physics, &mut b.physics);
+                }
+            }
+
+            for contact in contacts.iter_mut() {
+                solve_velocity(entities, contact);
+            }
+        }
+
+        for _ in 0..tunables.position_iterations {
+            for (_, joint) in joints.iter() {
+                if let Some((a, b)) = entities.get_pair_mut(joint.a, joint.b) {
+                    joint.solve_position(&mut a.physics, &mut b.physics, tunables);
+                }
+            }
+
+            for contact in &contacts {
+                solve_position(entities, contact, tunables);
+            }
+        }
+
+        self.cache = contacts.iter()
+            .map(|contact| ((contact.a, contact.b), (contact.normal_impulse, contact.tangent_impulse, contact.manifold.normal)))
+            .collect();
+    }
+
+    // Works out the masses along the normal and tangent, then applies last
+    // step's impulses again
+    fn prepare(&self, entities: &mut Store<Entity>, contact: &mut Contact, tunables: &Tunables) {
+        let (entity_a, entity_b) = entities.get_pair_mut(contact.a, contact.b).unwrap();
+        let (a, b) = (&mut entity_a.physics, &mut entity_b.physics);
+
+        let normal = contact.manifold.normal;
+        let tangent = contact.tangent();
+
+        contact.a_offset = contact.manifold.contact - a.position;
+        contact.b_offset = contact.manifold.contact - b.position;
+        contact.normal_mass = 1.0 / effective_inv_mass(a, b, contact.a_offset, contact.b_offset, normal);
+        contact.tangent_mass = 1.0 / effective_inv_mass(a, b, contact.a_offset, contact.b_offset, tangent);
+
+        let material = tunables.material(a.material).combine(&tunables.material(b.material));
+        let relative_velocity = b.velocity_at(contact.b_offset) - a.velocity_at(contact.a_offset);
+
+        let velocity_along_normal = relative_velocity.dot_product(normal);
+        contact.velocity_bias = if velocity_along_normal < -RESTITUTION_THRESHOLD {
+            -material.restitution * velocity_along_normal
+        } else {
+            0.0
+        };
+
+        contact.friction = if relative_velocity.dot_product(tangent).abs() < STATIC_FRICTION_THRESHOLD {
+            material.static_friction
+        } else {
+            material.dynamic_friction
+        };
+
+        if let Some(&(normal_impulse, tangent_impulse, previous_normal)) = self.cache.get(&(contact.a, contact.b)) {
+            if previous_normal.dot_product(normal) >= WARM_START_MIN_DOT {
+                contact.normal_impulse = normal_impulse;
+                contact.tangent_impulse = tangent_impulse;
+                apply_impulse(a, b, contact.a_offset, contact.b_offset, normal * normal_impulse + tangent * tangent_impulse);
+            }
+        }
+    }
+}
+
+fn is_wall(physics: &Physics) -> bool {
+    physics.is_static() && !physics.is_kinematic
+}
+
+// Sleeping bodies hold still like walls until something wakes them
+pub fn inv_mass(physics: &Physics) -> f32 {
+    if physics.is_sleeping { 0.0 } else { physics.inv_mass }
+}
+
+pub fn inv_inertia(physics: &Physics) -> f32 {
+    if physics.is_sleeping { 0.0 } else { physics.inv_inertia }
+}
+
+// How easily the two bodies' contact points move apart along the direction
+pub fn effective_inv_mass(a: &Physics, b: &Physics, a_offset: Vec2, b_offset: Vec2, direction: Vec2) -> f32 {
+    let a_turn = a_offset.cross_product(direction);
+    let b_turn = b_offset.cross_product(direction);
+
+    inv_mass(a) + inv_mass(b) + a_turn * a_turn * inv_inertia(a) + b_turn * b_turn * inv_inertia(b)
+}
+
+// Pushes b along the impulse and a the other way
+pub fn apply_impulse(a: &mut Physics, b: &mut Physics, a_offset: Vec2, b_offset: Vec2, impulse: Vec2) {
+    a.velocity -= inv_mass(a) * impulse;
+    a.angular_velocity -= inv_inertia(a) * a_offset.cross_product(impulse);
+    b.velocity += inv_mass(b) * impulse;
+    b.angular_velocity += inv_inertia(b) * b_offset.cross_product(impulse);
+}
+
+// The impulses built up so far are clamped rather than each iteration's,
+// so later iterations can take back some of what earlier ones overdid
+fn solve_velocity(entities: &mut Store<Entity>, contact: &mut Contact) {
+    let (entity_a, entity_b) = entities.get_pair_mut(contact.a, contact.b).unwrap();
+    let (a, b) = (&mut entity_a.physics, &mut entity_b.physics);
+
+    let normal = contact.manifold.normal;
+    let tangent = contact.tangent();
+
+    // Friction can't push harder than the bodies are pressed together
+    let relative_velocity = b.velocity_at(contact.b_offset) - a.velocity_at(contact.a_offset);
+    let max_friction = contact.friction * contact.normal_impulse;
+    let tangent_impulse = (contact.tangent_impulse - contact.tangent_mass * relative_velocity.dot_product(tangent))
+        .max(-max_friction)
+        .min(max_friction);
+    let change = tangent_impulse - contact.tangent_impulse;
+    contact.tangent_impulse = tangent_impulse;
+    apply_impulse(a, b, contact.a_offset, contact.b_offset, tangent * change);
+
+    // Contacts only ever push
+    let relative_velocity = b.velocity_at(contact.b_offset) - a.velocity_at(contact.a_offset);
+    let normal_impulse = (contact.normal_impulse - contact.normal_mass * (relative_velocity.dot_product(normal) - contact.velocity_bias))
+        .max(0.0);
+    let change = normal_impulse - contact.normal_impulse;
+    contact.normal_impulse = normal_impulse;
+    apply_impulse(a, b, contact.a_offset, contact.b_offset, normal * change);
+}
+
+// Pushes overlapping bodies apart, checking how far they overlap now that
+// earlier iterations moved them
+fn solve_position(entities: &mut Store<Entity>, contact: &Contact, tunables: &Tunables) {
+    let (entity_a, entity_b) = entities.get_pair_mut(contact.a, contact.b).unwrap();
+
+    let manifold = match collision_manifold(entity_a, entity_b) {
+        Some(manifold) => manifold,
+        None => return
+    };
+
+    let (a, b) = (&mut entity_a.physics, &mut entity_b.physics);
+    let (a_inv_mass, b_inv_mass) = (inv_mass(a), inv_mass(b));
+
+    let correction = (manifold.penetration - tunables.correction_slop).max(0.0) / (a_inv_mass + b_inv_mass) *
+        tunables.correction_percent * manifold.normal;
+
+    a.position -= a_inv_mass * correction;
+    b.position += b_inv_mass * correction;
+}
+
+// Anything awake pushing or pulling on a sleeping body wakes it up
+fn wake_touched(entities: &mut Store<Entity>, pairs: &[(EntityId, EntityId)], tunables: &Tunables) {
+    for &(a, b) in pairs {
+        let (entity_a, entity_b) = match entities.get_pair_mut(a, b) {
+            Some(pair) => pair,
+            None => continue
+        };
+        let (a, b) = (&mut entity_a.physics, &mut entity_b.physics);
+
+        if a.is_sleeping && is_pushing(b, tunables) {
+            a.wake();
+        } else if b.is_sleeping && is_pushing(a, tunables) {
+            b.wake();
+        }
+    }
+}
+
+fn is_pushing(physics: &Physics, tunables: &Tunables) -> bool {
+    if physics.is_kinematic {
+        physics.velocity.magnitude_squared() > 0.0 || physics.angular_velocity != 0.0
+    } else {
+        !physics.is_sleeping && !physics.is_static() && !physics.is_resting(tunables)
+    }
+}
@@ -0,0 +1,416 @@
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::render::{WindowCanvas, BlendMode};
+
+use vec2::Vec2;
+use entity::{Level, LevelStatus};
+use input::{InputState, Bindings};
+use replay::{Recorder, ReplayTick, Replay, Desync};
+use render::EntityRenderer;
+use game_state::{GameState, Transition};
+use level_file::load_level;
+use dungeon::generate;
+use event::dispatch;
+use score::Score;
+use tunables::Tunables;
+
+// Every level played is recorded so that bugs players hit can be replayed.
+// Each level of the session gets its own file, since replays start from a
+// fresh level.
+fn replay_path(level_number: u32) -> PathBuf {
+    PathBuf::from(format!("last_session_level_{}.replay", level_number))
+}
+
+// How long the level cleared banner stays up before the next level starts
+const LEVEL_TRANSITION_MS: u32 = 1500;
+
+const BACKGROUND: Color = Color { r: 88, g: 110, b: 117, a: 255 };
+const TEXT: Color = Color { r: 253, g: 246, b: 227, a: 255 };
+
+#[derive(Debug, Clone)]
+pub struct GameSettings {
+    pub width: f32,
+    pub height: f32,
+    pub seed: u64,
+    pub bindings: Bindings,
+    pub tunables: Tunables,
+    // Played as the first level instead of a generated one
+    pub level_path: Option<PathBuf>
+}
+
+impl GameSettings {
+    fn center(&self) -> Vec2 {
+        Vec2::new(self.width / 2.0, self.height / 2.0)
+    }
+}
+
+pub fn build_level(settings: &GameSettings, level_number: u32) -> Level {
+    let seed = settings.seed.wrapping_add(level_number as u64);
+
+    if let Some(ref path) = settings.level_path {
+        if level_number == 1 {
+            match load_level(path, seed, settings.tunables) {
+                Ok(level) => return level,
+                Err(e) => println!("Using a generated level: {}", e)
+            }
+        }
+    }
+
+    // Every level after the first brings one more enemy
+    generate(settings.width, settings.height, seed, settings.tunables, level_number)
+}
+
+fn draw_dimmed(canvas: &mut WindowCanvas) {
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+    canvas.fill_rect(None).expect("Draw didn't work");
+    canvas.set_blend_mode(BlendMode::None);
+}
+
+fn is_key_down(event: &Event, keys: &[Keycode]) -> bool {
+    match *event {
+        Event::KeyDown { keycode: Some(keycode), repeat: false, .. } => keys.contains(&keycode),
+        _ => false
+    }
+}
+
+pub struct TitleState {
+    settings: GameSettings
+}
+
+impl TitleState {
+    pub fn new(settings: GameSettings) -> TitleState {
+        TitleState { settings }
+    }
+}
+
+impl GameState for TitleState {
+    fn handle_event(&mut self, event: &Event) -> Transition {
+        if is_key_down(event, &[Keycode::Return, Keycode::Space]) {
+            Transition::Replace(Box::new(PlayingState::new(self.settings.clone(), 1, 0)))
+        } else if is_key_down(event, &[Keycode::Escape]) {
+            Transition::Quit
+        } else {
+            Transition::None
+        }
+    }
+
+    fn update(&mut self, _time_delta: u32) -> Transition {
+        Transition::None
+    }
+
+    fn render(&self, canvas: &mut WindowCanvas, _alpha: f32) {
+        let center = self.settings.center();
+
+        canvas.set_draw_color(BACKGROUND);
+        canvas.clear();
+
+        canvas.draw_text("ANOTHER ROGUE", center - Vec2::new(0.0, 40.0), TEXT);
+        canvas.draw_text("Press Enter to start", center, TEXT);
+        canvas.draw_text("Esc to quit", center + Vec2::new(0.0, 20.0), TEXT);
+    }
+
+    fn set_tunables(&mut self, tunables: &Tunables) {
+        self.settings.tunables = *tunables;
+    }
+}
+
+pub struct PlayingState {
+    settings: GameSettings,
+    level_number: u32,
+    level: Level,
+    score: Score,
+    input: InputState,
+    recorder: Option<Recorder<BufWriter<File>>>
+}
+
+impl PlayingState {
+    pub fn new(settings: GameSettings, level_number: u32, points: u32) -> PlayingState {
+        let level = build_level(&settings, level_number);
+
+        let mut score = Score::new(level.player_id);
+        score.points = points;
+
+        let path = replay_path(level_number);
+        let recorder = match Recorder::create(&path, &level) {
+            Ok(recorder) => Some(recorder),
+            Err(e) => {
+                println!("Not recording replay to {}: {}", path.display(), e);
+                None
+            }
+        };
+
+        PlayingState {
+            input: InputState::new(settings.bindings.clone()),
+            settings,
+            level_number,
+            level,
+            score,
+            recorder
+        }
+    }
+
+    // Losing the replay isn't worth stopping the game over
+    fn check_recording(&mut self, written: io::Result<()>) {
+        if let Err(e) = written {
+            println!("Stopped recording replay to {}: {}", replay_path(self.level_number).display(), e);
+            self.recorder = None;
+        }
+    }
+}
+
+impl GameState for PlayingState {
+    fn handle_event(&mut self, event: &Event) -> Transition {
+        match *event {
+            Event::KeyDown { keycode: Some(Keycode::Escape), .. } |
+            Event::Window { win_event: WindowEvent::FocusLost, .. } => {
+                self.input.release_all();
+                Transition::Push(Box::new(PausedState::new(self.settings.clone())))
+            }
+            _ => {
+                self.input.handle_event(event);
+                Transition::None
+            }
+        }
+    }
+
+    fn update(&mut self, time_delta: u32) -> Transition {
+        let commands = self.input.commands(self.level.tunables.acceleration);
+        self.level.step(&commands, time_delta);
+        dispatch(&self.level.events, &mut self.score);
+
+        let written = match self.recorder {
+            Some(ref mut recorder) => recorder.record(&commands, time_delta, &self.level),
+            None => Ok(())
+        };
+        self.check_recording(written);
+
+        match self.level.status() {
+            LevelStatus::Running => Transition::None,
+            LevelStatus::Cleared =>
+                Transition::Push(Box::new(LevelClearedState::new(self.settings.clone(), self.level_number, self.score.points))),
+            LevelStatus::PlayerDied =>
+                Transition::Push(Box::new(GameOverState::new(self.settings.clone(), self.level_number, self.score.points)))
+        }
+    }
+
+    fn render(&self, canvas: &mut WindowCanvas, alpha: f32) {
+        canvas.set_draw_color(BACKGROUND);
+        canvas.clear();
+
+        canvas.draw_level(&self.level, alpha);
+
+        let health = (self.level.player().health.map_or(0.0, |health| health.fraction()) * 100.0).ceil();
+        let hud = format!("Level {}   Health {}%   Score {}", self.level_number, health, self.score.points);
+        canvas.draw_text(&hud, Vec2::new(self.settings.width / 2.0, 10.0), TEXT);
+    }
+
+    fn set_tunables(&mut self, tunables: &Tunables) {
+        self.settings.tunables = *tunables;
+        self.level.set_tunables(*tunables);
+
+        let written = match self.recorder {
+            Some(ref mut recorder) => recorder.record_tunables(tunables),
+            None => Ok(())
+        };
+        self.check_recording(written);
+    }
+}
+
+pub struct PausedState {
+    settings: GameSettings
+}
+
+impl PausedState {
+    pub fn new(settings: GameSettings) -> PausedState {
+        PausedState { settings }
+    }
+}
+
+impl GameState for PausedState {
+    fn handle_event(&mut self, event: &Event) -> Transition {
+        if is_key_down(event, &[Keycode::Escape, Keycode::P, Keycode::Return]) {
+            Transition::Pop
+        } else if is_key_down(event, &[Keycode::Q]) {
+            Transition::Reset(Box::new(TitleState::new(self.settings.clone())))
+        } else {
+            Transition::None
+        }
+    }
+
+    fn update(&mut self, _time_delta: u32) -> Transition {
+        Transition::None
+    }
+
+    fn render(&self, canvas: &mut WindowCanvas, _alpha: f32) {
+        let center = self.settings.center();
+
+        draw_dimmed(canvas);
+        canvas.draw_text("PAUSED", center - Vec2::new(0.0, 20.0), TEXT);
+        canvas.draw_text("Esc to resume, Q to give up", center, TEXT);
+    }
+
+    fn is_overlay(&self) -> bool {
+        true
+    }
+
+    fn set_tunables(&mut self, tunables: &Tunables) {
+        self.settings.tunables = *tunables;
+    }
+}
+
+pub struct LevelClearedState {
+    settings: GameSettings,
+    level_number: u32,
+    points: u32,
+    time_running: u32
+}
+
+impl LevelClearedState {
+    pub fn new(settings: GameSettings, level_number: u32, points: u32) -> LevelClearedState {
+        LevelClearedState { settings, level_number, points, time_running: 0 }
+    }
+}
+
+impl GameState for LevelClearedState {
+    fn handle_event(&mut self, _event: &Event) -> Transition {
+        Transition::None
+    }
+
+    fn update(&mut self, time_delta: u32) -> Transition {
+        self.time_running += time_delta;
+
+        if self.time_running >= LEVEL_TRANSITION_MS {
+            Transition::Reset(Box::new(PlayingState::new(self.settings.clone(), self.level_number + 1, self.points)))
+        } else {
+            Transition::None
+        }
+    }
+
+    fn render(&self, canvas: &mut WindowCanvas, _alpha: f32) {
+        draw_dimmed(canvas);
+        canvas.draw_text(&format!("Level {} cleared", self.level_number), self.settings.center(), TEXT);
+    }
+
+    fn is_overlay(&self) -> bool {
+        true
+    }
+
+    fn set_tunables(&mut self, tunables: &Tunables) {
+        self.settings.tunables = *tunables;
+    }
+}
+
+pub struct GameOverState {
+    settings: GameSettings,
+    level_number: u32,
+    points: u32
+}
+
+impl GameOverState {
+    pub fn new(settings: GameSettings, level_number: u32, points: u32) -> GameOverState {
+        GameOverState { settings, level_number, points }
+    }
+}
+
+impl GameState for GameOverState {
+    fn handle_event(&mut self, event: &Event) -> Transition {
+        if is_key_down(event, &[Keycode::Return, Keycode::Space]) {
+            Transition::Reset(Box::new(PlayingState::new(self.settings.clone(), 1, 0)))
+        } else if is_key_down(event, &[Keycode::Escape]) {
+            Transition::Reset(Box::new(TitleState::new(self.settings.clone())))
+        } else {
+            Transition::None
+        }
+    }
+
+    fn update(&mut self, _time_delta: u32) -> Transition {
+        Transition::None
+    }
+
+    fn render(&self, canvas: &mut WindowCanvas, _alpha: f32) {
+        let center = self.settings.center();
+
+        draw_dimmed(canvas);
+        canvas.draw_text("GAME OVER", center - Vec2::new(0.0, 20.0), TEXT);
+        canvas.draw_text(&format!("You made it to level {} with {} points", self.level_number, self.points), center, TEXT);
+        canvas.draw_text("Enter to try again, Esc for the title screen", center + Vec2::new(0.0, 20.0), TEXT);
+    }
+
+    fn is_overlay(&self) -> bool {
+        true
+    }
+
+    fn set_tunables(&mut self, tunables: &Tunables) {
+        self.settings.tunables = *tunables;
+    }
+}
+
+// Plays a recorded replay back tick by tick, stopping if the simulation
+// stops matching the recording
+pub struct ReplayState {
+    settings: GameSettings,
+    level: Level,
+    ticks: Vec<ReplayTick>,
+    next_tick: usize,
+    desync: Option<Desync>
+}
+
+impl ReplayState {
+    pub fn new(settings: GameSettings, replay: Replay) -> ReplayState {
+        ReplayState {
+            settings,
+            level: replay.level,
+            ticks: replay.ticks,
+            next_tick: 0,
+            desync: None
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.desync.is_some() || self.next_tick >= self.ticks.len()
+    }
+}
+
+impl GameState for ReplayState {
+    fn handle_event(&mut self, event: &Event) -> Transition {
+        if is_key_down(event, &[Keycode::Escape]) {
+            Transition::Quit
+        } else {
+            Transition::None
+        }
+    }
+
+    fn update(&mut self, _time_delta: u32) -> Transition {
+        if !self.is_finished() {
+            let tick = &self.ticks[self.next_tick];
+            if let Err(actual) = tick.run(&mut self.level) {
+                self.desync = Some(Desync { tick: self.next_tick, expected: tick.checksum, actual });
+            }
+            self.next_tick += 1;
+        }
+
+        Transition::None
+    }
+
+    fn render(&self, canvas: &mut WindowCanvas, alpha: f32) {
+        canvas.set_draw_color(BACKGROUND);
+        canvas.clear();
+
+        let alpha = if self.is_finished() { 1.0 } else { alpha };
+        canvas.draw_level(&self.level, alpha);
+
+        let hud = match self.desync {
+            Some(desync) => format!("Replay desynced at tick {}   Esc to quit", desync.tick),
+            None if self.is_finished() => format!("Replay finished after {} ticks   Esc to quit", self.ticks.len()),
+            None => format!("Replay tick {} of {}", self.next_tick, self.ticks.len())
+        };
+        canvas.draw_text(&hud, Vec2::new(self.settings.width / 2.0, 10.0), TEXT);
+    }
+}
@@ -0,0 +1,196 @@
+use std::marker::PhantomData;
+
+// Handle to something in a Store. The generation changes every time a slot
+// is reused, so a handle to something that was removed never finds
+// whatever took its place.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EntityId {
+    pub index: u32,
+    pub generation: u32
+}
+
+// Each kind of thing kept in a Store gets its own kind of handle, so a
+// handle to one kind can't be used to look up another
+pub trait Id: Copy {
+    fn new(index: u32, generation: u32) -> Self;
+    fn index(&self) -> u32;
+    fn generation(&self) -> u32;
+}
+
+impl Id for EntityId {
+    fn new(index: u32, generation: u32) -> EntityId {
+        EntityId { index, generation }
+    }
+
+    fn index(&self) -> u32 {
+        self.index
+    }
+
+    fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Slot<T> {
+    generation: u32,
+    value: Option<T>
+}
+
+// Generational arena. Iteration goes in slot order and freed slots are
+// reused last in, first out, so the same inserts and removes always hand
+// out the same ids.
+#[derive(Debug, Clone)]
+pub struct Store<T, I = EntityId> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>,
+    len: usize,
+    ids: PhantomData<I>
+}
+
+impl<T, I: Id> Default for Store<T, I> {
+    fn default() -> Store<T, I> {
+        Store::new()
+    }
+}
+
+impl<T, I: Id> Store<T, I> {
+    pub fn new() -> Store<T, I> {
+        Store { slots: vec![], free: vec![], len: 0, ids: PhantomData }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn insert(&mut self, value: T) -> I {
+        self.len += 1;
+
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.value = Some(value);
+                I::new(index, slot.generation)
+            }
+            None => {
+                self.slots.push(Slot { generation: 0, value: Some(value) });
+                I::new(self.slots.len() as u32 - 1, 0)
+            }
+        }
+    }
+
+    pub fn remove(&mut self, id: I) -> Option<T> {
+        if !self.contains(id) {
+            return None;
+        }
+
+        let slot = &mut self.slots[id.index() as usize];
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(id.index());
+        self.len -= 1;
+
+        slot.value.take()
+    }
+
+    pub fn contains(&self, id: I) -> bool {
+        self.get(id).is_some()
+    }
+
+    pub fn get(&self, id: I) -> Option<&T> {
+        match self.slots.get(id.index() as usize) {
+            Some(slot) if slot.generation == id.generation() => slot.value.as_ref(),
+            _ => None
+        }
+    }
+
+    pub fn get_mut(&mut self, id: I) -> Option<&mut T> {
+        match self.slots.get_mut(id.index() as usize) {
+            Some(slot) if slot.generation == id.generation() => slot.value.as_mut(),
+            _ => None
+        }
+    }
+
+    // Two different values borrowed mutably at once, for pairwise work like
+    // collision resolution
+    pub fn get_pair_mut(&mut self, a: I, b: I) -> Option<(&mut T, &mut T)> {
+        if a.index() == b.index() || !self.contains(a) || !self.contains(b) {
+            return None;
+        }
+
+        let (low, high) = if a.index() < b.index() { (a, b) } else { (b, a) };
+        let (head, tail) = self.slots.split_at_mut(high.index() as usize);
+        let low_value = head[low.index() as usize].value.as_mut().unwrap();
+        let high_value = tail[0].value.as_mut().unwrap();
+
+        if a.index() < b.index() {
+            Some((low_value, high_value))
+        } else {
+            Some((high_value, low_value))
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=(I, &T)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            slot.value.as_ref().map(|value| (I::new(index as u32, slot.generation), value))
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item=(I, &mut T)> {
+        self.slots.iter_mut().enumerate().filter_map(|(index, slot)| {
+            let generation = slot.generation;
+            slot.value.as_mut().map(|value| (I::new(index as u32, generation), value))
+        })
+    }
+
+    pub fn ids(&self) -> Vec<I> {
+        self.iter().map(|(id, _)| id).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removed_slots_are_reused_with_a_new_generation() {
+        let mut store: Store<&str> = Store::new();
+        let a = store.insert("a");
+        let b = store.insert("b");
+
+        assert_eq!(store.remove(a), Some("a"));
+        assert_eq!(store.remove(a), None);
+
+        let c = store.insert("c");
+        assert_eq!(c.index, a.index);
+        assert_eq!(c.generation, a.generation + 1);
+
+        // The old handle doesn't reach what took its place
+        assert_eq!(store.get(a), None);
+        assert_eq!(store.get(c), Some(&"c"));
+        assert_eq!(store.get(b), Some(&"b"));
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.ids(), vec![c, b]);
+    }
+
+    #[test]
+    fn pairs_need_two_live_values() {
+        let mut store: Store<u32> = Store::new();
+        let a = store.insert(1);
+        let b = store.insert(2);
+
+        {
+            let (b_value, a_value) = store.get_pair_mut(b, a).unwrap();
+            *b_value += 10;
+            *a_value += 20;
+        }
+        assert_eq!((store.get(a), store.get(b)), (Some(&21), Some(&12)));
+
+        assert!(store.get_pair_mut(a, a).is_none());
+        store.remove(b);
+        assert!(store.get_pair_mut(a, b).is_none());
+    }
+}
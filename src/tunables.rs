@@ -0,0 +1,422 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use material::{Material, MaterialProperties};
+use shield::MAX_SHIELD_SLICES;
+
+// Past this the frame limiter can't keep up and ticks get too short to
+// matter
+pub const MAX_FPS: u32 = 200;
+
+// More solver passes than this only slow things down
+const MAX_ITERATIONS: u32 = 100;
+
+// Everything a designer might want to tweak about how the game plays. Speeds
+// are per second, times in ms and masses are turned into inverse masses
+// when bodies are made.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Tunables {
+    pub fps: u32,
+
+    // Player movement, pixels per second squared
+    pub acceleration: f32,
+    // How fast velocity decays, per second. Coasting, it shrinks by a
+    // factor of about e^drag every second, and under acceleration it
+    // levels off at acceleration / drag.
+    pub drag: f32,
+
+    pub player_radius: f32,
+    pub player_mass: f32,
+    pub player_health: f32,
+
+    pub boom_charge_time: u32,
+    pub pew_pew_radius: f32,
+    pub pew_pew_speed: f32,
+    pub pew_pew_damage: f32,
+    pub boom_radius: f32,
+    pub boom_speed: f32,
+    pub boom_damage: f32,
+    // Shield health a boom knocks off the slice it hits
+    pub boom_shield_damage: f32,
+
+    pub enemy_radius: f32,
+    pub enemy_mass: f32,
+    pub enemy_health: f32,
+    // Player health lost per second of touching an enemy
+    pub enemy_contact_damage: f32,
+    pub shield_slices: u32,
+    // Shield health each slice gets back per second
+    pub shield_regen: f32,
+
+    pub crate_mass: f32,
+    pub turret_charge_time: u32,
+    // How far something can be squeezed into a moving wall before it's
+    // crushed
+    pub crush_depth: f32,
+
+    // Fraction of the overlap pushed apart each tick, and how much overlap
+    // is left alone so resting bodies don't jitter
+    pub correction_percent: f32,
+    pub correction_slop: f32,
+
+    // Contact solver passes per tick over velocities, then positions
+    pub solver_iterations: u32,
+    pub position_iterations: u32,
+
+    // Bodies slower than this for sleep_time ms stop being simulated until
+    // something pushes them
+    pub sleep_speed: f32,
+    pub sleep_angular_speed: f32,
+    pub sleep_time: u32,
+
+    // Materials, see material.rs
+    pub flesh_restitution: f32,
+    pub flesh_static_friction: f32,
+    pub flesh_dynamic_friction: f32,
+
+    pub stone_restitution: f32,
+    pub stone_static_friction: f32,
+    pub stone_dynamic_friction: f32,
+
+    pub rubber_restitution: f32,
+    pub rubber_static_friction: f32,
+    pub rubber_dynamic_friction: f32,
+
+    pub wood_restitution: f32,
+    pub wood_static_friction: f32,
+    pub wood_dynamic_friction: f32,
+
+    pub metal_restitution: f32,
+    pub metal_static_friction: f32,
+    pub metal_dynamic_friction: f32
+}
+
+impl Default for Tunables {
+    fn default() -> Tunables {
+        Tunables::new()
+    }
+}
+
+impl Tunables {
+    pub fn new() -> Tunables {
+        Tunables {
+            fps: 60,
+
+            acceleration: 3600.0,
+            drag: 6.0,
+
+            player_radius: 10.0,
+            player_mass: 20.0,
+            player_health: 1.0,
+
+            boom_charge_time: 1000,
+            pew_pew_radius: 2.0,
+            pew_pew_speed: 1200.0,
+            pew_pew_damage: 0.2,
+            boom_radius: 4.0,
+            boom_speed: 720.0,
+            boom_damage: 0.5,
+            boom_shield_damage: 0.75,
+
+            enemy_radius: 20.0,
+            enemy_mass: 50.0,
+            enemy_health: 1.0,
+            enemy_contact_damage: 0.5,
+            shield_slices: 12,
+            shield_regen: 0.12,
+
+            crate_mass: 40.0,
+            turret_charge_time: 600,
+            crush_depth: 4.0,
+
+            correction_percent: 0.8,
+            correction_slop: 0.1,
+
+            solver_iterations: 8,
+            position_iterations: 3,
+
+            sleep_speed: 5.0,
+            sleep_angular_speed: 0.1,
+            sleep_time: 500,
+
+            flesh_restitution: 0.3,
+            flesh_static_friction: 0.4,
+            flesh_dynamic_friction: 0.2,
+
+            stone_restitution: 0.2,
+            stone_static_friction: 0.6,
+            stone_dynamic_friction: 0.4,
+
+            rubber_restitution: 1.0,
+            rubber_static_friction: 0.9,
+            rubber_dynamic_friction: 0.7,
+
+            wood_restitution: 0.1,
+            wood_static_friction: 0.5,
+            wood_dynamic_friction: 0.3,
+
+            metal_restitution: 0.4,
+            metal_static_friction: 0.2,
+            metal_dynamic_friction: 0.1
+        }
+    }
+
+    // Same "name = value" format as the controls file. Anything left out
+    // keeps its default.
+    pub fn parse(config: &str) -> Result<Tunables, String> {
+        let mut tunables = Tunables::new();
+
+        for (line_number, line) in config.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let name = parts.next().unwrap().trim();
+            let value = parts.next().ok_or(format!("Line {}: expected 'name = value'", line_number + 1))?.trim();
+
+            tunables.set(name, value).map_err(|e| format!("Line {}: {}", line_number + 1, e))?;
+        }
+
+        Ok(tunables)
+    }
+
+    pub fn load(path: &Path) -> Result<Tunables, String> {
+        let mut contents = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut contents))
+            .map_err(|e| format!("Couldn't read tunables {}: {}", path.display(), e))?;
+
+        Tunables::parse(&contents)
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match name {
+            "fps" => self.fps = parse_u32_in(name, value, 1, MAX_FPS)?,
+
+            "acceleration" => self.acceleration = parse_non_negative(name, value)?,
+            "drag" => self.drag = parse_non_negative(name, value)?,
+
+            "player_radius" => self.player_radius = parse_positive(name, value)?,
+            "player_mass" => self.player_mass = parse_positive(name, value)?,
+            "player_health" => self.player_health = parse_positive(name, value)?,
+
+            "boom_charge_time" => self.boom_charge_time = parse_u32(name, value)?,
+            "pew_pew_radius" => self.pew_pew_radius = parse_positive(name, value)?,
+            "pew_pew_speed" => self.pew_pew_speed = parse_non_negative(name, value)?,
+            "pew_pew_damage" => self.pew_pew_damage = parse_non_negative(name, value)?,
+            "boom_radius" => self.boom_radius = parse_positive(name, value)?,
+            "boom_speed" => self.boom_speed = parse_non_negative(name, value)?,
+            "boom_damage" => self.boom_damage = parse_non_negative(name, value)?,
+            "boom_shield_damage" => self.boom_shield_damage = parse_non_negative(name, value)?,
+
+            "enemy_radius" => self.enemy_radius = parse_positive(name, value)?,
+            "enemy_mass" => self.enemy_mass = parse_positive(name, value)?,
+            "enemy_health" => self.enemy_health = parse_positive(name, value)?,
+            "enemy_contact_damage" => self.enemy_contact_damage = parse_non_negative(name, value)?,
+            "shield_slices" => self.shield_slices = parse_u32_in(name, value, 1, MAX_SHIELD_SLICES as u32)?,
+            "shield_regen" => self.shield_regen = parse_non_negative(name, value)?,
+
+            "crate_mass" => self.crate_mass = parse_positive(name, value)?,
+            "turret_charge_time" => self.turret_charge_time = parse_u32(name, value)?,
+            "crush_depth" => self.crush_depth = parse_non_negative(name, value)?,
+
+            "correction_percent" => self.correction_percent = parse_fraction(name, value)?,
+            "correction_slop" => self.correction_slop = parse_non_negative(name, value)?,
+            "solver_iterations" => self.solver_iterations = parse_u32_in(name, value, 1, MAX_ITERATIONS)?,
+            "position_iterations" => self.position_iterations = parse_u32_in(name, value, 1, MAX_ITERATIONS)?,
+            "sleep_speed" => self.sleep_speed = parse_non_negative(name, value)?,
+            "sleep_angular_speed" => self.sleep_angular_speed = parse_non_negative(name, value)?,
+            "sleep_time" => self.sleep_time = parse_u32(name, value)?,
+
+            "flesh_restitution" => self.flesh_restitution = parse_non_negative(name, value)?,
+            "flesh_static_friction" => self.flesh_static_friction = parse_non_negative(name, value)?,
+            "flesh_dynamic_friction" => self.flesh_dynamic_friction = parse_non_negative(name, value)?,
+
+            "stone_restitution" => self.stone_restitution = parse_non_negative(name, value)?,
+            "stone_static_friction" => self.stone_static_friction = parse_non_negative(name, value)?,
+            "stone_dynamic_friction" => self.stone_dynamic_friction = parse_non_negative(name, value)?,
+
+            "rubber_restitution" => self.rubber_restitution = parse_non_negative(name, value)?,
+            "rubber_static_friction" => self.rubber_static_friction = parse_non_negative(name, value)?,
+            "rubber_dynamic_friction" => self.rubber_dynamic_friction = parse_non_negative(name, value)?,
+
+            "wood_restitution" => self.wood_restitution = parse_non_negative(name, value)?,
+            "wood_static_friction" => self.wood_static_friction = parse_non_negative(name, value)?,
+            "wood_dynamic_friction" => self.wood_dynamic_friction = parse_non_negative(name, value)?,
+
+            "metal_restitution" => self.metal_restitution = parse_non_negative(name, value)?,
+            "metal_static_friction" => self.metal_static_friction = parse_non_negative(name, value)?,
+            "metal_dynamic_friction" => self.metal_dynamic_friction = parse_non_negative(name, value)?,
+
+            _ => return Err(format!("unknown tunable '{}'", name))
+        }
+
+        Ok(())
+    }
+
+    // The material table, with restitution kept between 0 and 1
+    pub fn material(&self, material: Material) -> MaterialProperties {
+        match material {
+            Material::Flesh => MaterialProperties::new(self.flesh_restitution, self.flesh_static_friction, self.flesh_dynamic_friction),
+            Material::Stone => MaterialProperties::new(self.stone_restitution, self.stone_static_friction, self.stone_dynamic_friction),
+            Material::Rubber => MaterialProperties::new(self.rubber_restitution, self.rubber_static_friction, self.rubber_dynamic_friction),
+            Material::Wood => MaterialProperties::new(self.wood_restitution, self.wood_static_friction, self.wood_dynamic_friction),
+            Material::Metal => MaterialProperties::new(self.metal_restitution, self.metal_static_friction, self.metal_dynamic_friction)
+        }
+    }
+
+    // Writes every value in the format parse reads, used to keep replays
+    // independent of the tunables file
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "fps = {}", self.fps)?;
+
+        writeln!(out, "acceleration = {}", self.acceleration)?;
+        writeln!(out, "drag = {}", self.drag)?;
+
+        writeln!(out, "player_radius = {}", self.player_radius)?;
+        writeln!(out, "player_mass = {}", self.player_mass)?;
+        writeln!(out, "player_health = {}", self.player_health)?;
+
+        writeln!(out, "boom_charge_time = {}", self.boom_charge_time)?;
+        writeln!(out, "pew_pew_radius = {}", self.pew_pew_radius)?;
+        writeln!(out, "pew_pew_speed = {}", self.pew_pew_speed)?;
+        writeln!(out, "pew_pew_damage = {}", self.pew_pew_damage)?;
+        writeln!(out, "boom_radius = {}", self.boom_radius)?;
+        writeln!(out, "boom_speed = {}", self.boom_speed)?;
+        writeln!(out, "boom_damage = {}", self.boom_damage)?;
+        writeln!(out, "boom_shield_damage = {}", self.boom_shield_damage)?;
+
+        writeln!(out, "enemy_radius = {}", self.enemy_radius)?;
+        writeln!(out, "enemy_mass = {}", self.enemy_mass)?;
+        writeln!(out, "enemy_health = {}", self.enemy_health)?;
+        writeln!(out, "enemy_contact_damage = {}", self.enemy_contact_damage)?;
+        writeln!(out, "shield_slices = {}", self.shield_slices)?;
+        writeln!(out, "shield_regen = {}", self.shield_regen)?;
+
+        writeln!(out, "crate_mass = {}", self.crate_mass)?;
+        writeln!(out, "turret_charge_time = {}", self.turret_charge_time)?;
+        writeln!(out, "crush_depth = {}", self.crush_depth)?;
+
+        writeln!(out, "correction_percent = {}", self.correction_percent)?;
+        writeln!(out, "correction_slop = {}", self.correction_slop)?;
+
+        writeln!(out, "solver_iterations = {}", self.solver_iterations)?;
+        writeln!(out, "position_iterations = {}", self.position_iterations)?;
+        writeln!(out, "sleep_speed = {}", self.sleep_speed)?;
+        writeln!(out, "sleep_angular_speed = {}", self.sleep_angular_speed)?;
+        writeln!(out, "sleep_time = {}", self.sleep_time)?;
+
+        writeln!(out, "flesh_restitution = {}", self.flesh_restitution)?;
+        writeln!(out, "flesh_static_friction = {}", self.flesh_static_friction)?;
+        writeln!(out, "flesh_dynamic_friction = {}", self.flesh_dynamic_friction)?;
+
+        writeln!(out, "stone_restitution = {}", self.stone_restitution)?;
+        writeln!(out, "stone_static_friction = {}", self.stone_static_friction)?;
+        writeln!(out, "stone_dynamic_friction = {}", self.stone_dynamic_friction)?;
+
+        writeln!(out, "rubber_restitution = {}", self.rubber_restitution)?;
+        writeln!(out, "rubber_static_friction = {}", self.rubber_static_friction)?;
+        writeln!(out, "rubber_dynamic_friction = {}", self.rubber_dynamic_friction)?;
+
+        writeln!(out, "wood_restitution = {}", self.wood_restitution)?;
+        writeln!(out, "wood_static_friction = {}", self.wood_static_friction)?;
+        writeln!(out, "wood_dynamic_friction = {}", self.wood_dynamic_friction)?;
+
+        writeln!(out, "metal_restitution = {}", self.metal_restitution)?;
+        writeln!(out, "metal_static_friction = {}", self.metal_static_friction)?;
+        writeln!(out, "metal_dynamic_friction = {}", self.metal_dynamic_friction)
+    }
+}
+
+fn parse_f32(name: &str, value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(number) if number.is_finite() => Ok(number),
+        _ => Err(format!("{} needs a number, got '{}'", name, value))
+    }
+}
+
+// Out of range values are errors rather than clamped, so a bad hot reload
+// keeps the tunables the game was already running with
+fn parse_positive(name: &str, value: &str) -> Result<f32, String> {
+    let number = parse_f32(name, value)?;
+    if number > 0.0 { Ok(number) } else { Err(format!("{} needs to be above 0, got {}", name, number)) }
+}
+
+fn parse_non_negative(name: &str, value: &str) -> Result<f32, String> {
+    let number = parse_f32(name, value)?;
+    if number >= 0.0 { Ok(number) } else { Err(format!("{} can't be negative, got {}", name, number)) }
+}
+
+fn parse_fraction(name: &str, value: &str) -> Result<f32, String> {
+    let number = parse_f32(name, value)?;
+    if (0.0..=1.0).contains(&number) { Ok(number) } else { Err(format!("{} needs to be between 0 and 1, got {}", name, number)) }
+}
+
+fn parse_u32(name: &str, value: &str) -> Result<u32, String> {
+    value.parse::<u32>().map_err(|_| format!("{} needs a whole number, got '{}'", name, value))
+}
+
+fn parse_u32_in(name: &str, value: &str, min: u32, max: u32) -> Result<u32, String> {
+    let number = parse_u32(name, value)?;
+    if (min..=max).contains(&number) { Ok(number) } else { Err(format!("{} needs to be between {} and {}, got {}", name, min, max, number)) }
+}
+
+// Notices when the tunables file is saved so the game can pick up the new
+// values while it's running
+pub struct TunablesWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>
+}
+
+impl TunablesWatcher {
+    pub fn new(path: &Path) -> TunablesWatcher {
+        TunablesWatcher { path: path.to_path_buf(), modified: modified_time(path) }
+    }
+
+    // Only returns something when the file changed since the last poll
+    pub fn poll(&mut self) -> Option<Result<Tunables, String>> {
+        let modified = modified_time(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return None;
+        }
+
+        self.modified = modified;
+        Some(Tunables::load(&self.path))
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_out_of_range_are_errors() {
+        assert!(Tunables::parse("fps = 0").is_err());
+        assert!(Tunables::parse("fps = 500").is_err());
+        assert!(Tunables::parse("player_mass = 0").is_err());
+        assert!(Tunables::parse("crate_mass = -40").is_err());
+        assert!(Tunables::parse("drag = -1").is_err());
+        assert!(Tunables::parse("drag = NaN").is_err());
+        assert!(Tunables::parse("solver_iterations = 0").is_err());
+        assert!(Tunables::parse("position_iterations = 100000").is_err());
+        assert!(Tunables::parse("correction_percent = 2").is_err());
+        assert!(Tunables::parse("fps = 200\ndrag = 0").is_ok());
+    }
+
+    #[test]
+    fn defaults_are_in_range() {
+        let mut out = vec![];
+        Tunables::new().write(&mut out).unwrap();
+        assert_eq!(Tunables::parse(&String::from_utf8(out).unwrap()), Ok(Tunables::new()));
+    }
+}
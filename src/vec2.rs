@@ -11,7 +11,7 @@ pub struct Vec2 {
 
 impl Vec2 {
     pub fn new(x: f32, y: f32) -> Vec2 {
-        Vec2 { x: x, y: y }
+        Vec2 { x, y }
     }
     
     pub fn from_ints(x: i32, y: i32) -> Vec2 {
@@ -41,6 +41,22 @@ impl Vec2 {
         let m = self.magnitude();
         Vec2::new(self.x / m, self.y / m)
     }
+
+    // Z component of the 3D cross product, how much turning one vector takes
+    // to line it up with the other
+    pub fn cross_product(&self, other: Vec2) -> f32 {
+        (self.x * other.y) - (self.y * other.x)
+    }
+
+    // Turned a quarter, the same way positive angles turn
+    pub fn perpendicular(&self) -> Vec2 {
+        Vec2::new(-self.y, self.x)
+    }
+
+    pub fn rotate(&self, angle: f32) -> Vec2 {
+        let (sin, cos) = angle.sin_cos();
+        Vec2::new(self.x * cos - self.y * sin, self.x * sin + self.y * cos)
+    }
 }
 
 impl Add for Vec2 {